@@ -0,0 +1,65 @@
+# Raven shell integration for bash.
+# Reports prompts, commands and their exit status (OSC 133) and the current
+# working directory (OSC 7) to the terminal.
+
+if [[ "$-" != *i* ]] || [[ -n "$RAVEN_SHELL_INTEGRATION_LOADED" ]]; then
+    return
+fi
+RAVEN_SHELL_INTEGRATION_LOADED=1
+
+__raven_at_prompt=
+__raven_command_running=
+
+__raven_precmd() {
+    local ret=$?
+    if [[ -n "$__raven_command_running" ]]; then
+        builtin printf '\e]133;D;%s\a' "$ret"
+        __raven_command_running=
+    fi
+    # Keep the exit status for the rest of PROMPT_COMMAND
+    return $ret
+}
+
+__raven_prompt() {
+    builtin printf '\e]7;file://%s%s\a' "$HOSTNAME" "$PWD"
+    builtin printf '\e]133;A\a'
+    # Marked here rather than once, as the prompt may be set again at any time
+    if [[ "$PS1" != *'\[\e]133;B\a\]' ]]; then
+        PS1="$PS1\[\e]133;B\a\]"
+    fi
+    __raven_at_prompt=1
+}
+
+__raven_preexec() {
+    local ret=$?
+    # The DEBUG trap also runs for PROMPT_COMMAND and completions
+    if [[ -n "$__raven_at_prompt" && -z "$COMP_LINE" ]]; then
+        __raven_at_prompt=
+        __raven_command_running=1
+        builtin printf '\e]133;C\a'
+    fi
+    # Keep the exit status for a DEBUG trap that was set before
+    return $ret
+}
+
+# Sourced files and functions don't see the DEBUG trap set before, so it's
+# read from PROMPT_COMMAND at the first prompt and chained from then on
+__raven_install_debug_trap() {
+    # `trap -p` prints `trap -- '<command>' DEBUG`
+    builtin eval "local previous=($1)"
+    __raven_previous_debug_trap=${previous[2]}
+    builtin trap '__raven_preexec; builtin eval "$__raven_previous_debug_trap"' DEBUG
+    __raven_debug_trap_installed=1
+}
+__raven_install='[[ -n "$__raven_debug_trap_installed" ]] || __raven_install_debug_trap "$(builtin trap -p DEBUG)"'
+
+if [[ -n "$bash_preexec_imported" || -n "$__bp_imported" ]]; then
+    # bash-preexec owns the DEBUG trap and PROMPT_COMMAND
+    precmd_functions=(__raven_precmd "${precmd_functions[@]}" __raven_prompt)
+    preexec_functions+=(__raven_preexec)
+elif [[ "$(builtin declare -p PROMPT_COMMAND 2>/dev/null)" == "declare -a"* ]]; then
+    # bash 5.1 and later run every element of an array
+    PROMPT_COMMAND=(__raven_precmd "${PROMPT_COMMAND[@]}" "$__raven_install" __raven_prompt)
+else
+    PROMPT_COMMAND="__raven_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}; $__raven_install; __raven_prompt"
+fi
@@ -0,0 +1,20 @@
+# Raven shell integration for fish.
+# Reports prompts, commands and their exit status (OSC 133) and the current
+# working directory (OSC 7) to the terminal.
+
+status is-interactive; or exit
+set -q RAVEN_SHELL_INTEGRATION_LOADED; and exit
+set -g RAVEN_SHELL_INTEGRATION_LOADED 1
+
+function __raven_prompt --on-event fish_prompt
+    printf '\e]7;file://%s%s\a' (hostname) "$PWD"
+    printf '\e]133;A\a'
+end
+
+function __raven_preexec --on-event fish_preexec
+    printf '\e]133;C\a'
+end
+
+function __raven_postexec --on-event fish_postexec
+    printf '\e]133;D;%s\a' $status
+end
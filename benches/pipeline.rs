@@ -0,0 +1,244 @@
+//! Throughput of each stage content goes through, from the bytes read from
+//! the PTY to the laid out lines painted by the UI:
+//!
+//! parse -> perform_actions -> render_terminal -> clusters -> layout_lines
+//!
+//! Runs headlessly with `cargo bench`. Each stage is fed with synthetic
+//! vtebench-style streams, plus the raw output of a program recorded to the
+//! file in `RAVEN_BENCH_RECORDING`, if set (e.g. `script -q -c 'cargo build' build.log`).
+
+use std::{fmt::Write as _, sync::Arc};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use termwiz::escape::{parser::Parser, Action};
+use wezterm_term::{color::ColorPalette, Terminal, TerminalConfiguration, TerminalSize};
+
+use raven::{
+    config::FontConfig,
+    fonts,
+    layout::{LayoutSettings, LineLayoutCache},
+    rendering::{render_terminal, LineElement, RenderedRows},
+    utils::get_cell_size,
+};
+
+const COLS: usize = 120;
+const ROWS: usize = 40;
+
+// Size of the reads of the PTY
+const READ_SIZE: usize = 4096;
+
+// Approximate size of each generated stream
+const STREAM_SIZE: usize = 512 * 1024;
+
+const FONT_SIZE: f32 = 14.;
+
+#[derive(Debug)]
+struct BenchConfig;
+
+impl TerminalConfiguration for BenchConfig {
+    fn color_palette(&self) -> ColorPalette {
+        ColorPalette::default()
+    }
+}
+
+/// Lines of plain text scrolling the screen, like `cat` of a big file.
+fn dense_scrolling() -> Vec<u8> {
+    let words = [
+        "lorem",
+        "ipsum",
+        "dolor",
+        "sit",
+        "amet",
+        "consectetur",
+        "adipiscing",
+    ];
+
+    let mut stream = String::new();
+    let mut line = 0;
+    while stream.len() < STREAM_SIZE {
+        let mut text = format!("{:>6} ", line);
+        let mut word = line;
+        while text.len() + 12 < COLS {
+            text.push_str(words[word % words.len()]);
+            text.push(' ');
+            word += 3;
+        }
+        stream.push_str(&text);
+        stream.push_str("\r\n");
+        line += 1;
+    }
+    stream.into_bytes()
+}
+
+/// Wide, combining and emoji characters, along with box drawing.
+fn unicode() -> Vec<u8> {
+    let samples = [
+        "日本語のテキスト",
+        "한국어 텍스트",
+        "e\u{301}te\u{301} nai\u{308}ve",
+        "🦀 🚀 👩‍💻 🇫🇷",
+        "┌─┬─┐│ ││ │└─┴─┘",
+        "⠁⠃⠉⠙⠑⠋⠛⠓",
+        "Ωμέγα Ελληνικά",
+    ];
+
+    let mut stream = String::new();
+    let mut line = 0;
+    while stream.len() < STREAM_SIZE {
+        for sample in samples.iter().cycle().skip(line % samples.len()).take(4) {
+            stream.push_str(sample);
+            stream.push(' ');
+        }
+        stream.push_str("\r\n");
+        line += 1;
+    }
+    stream.into_bytes()
+}
+
+/// Every cell in its own colours and attributes, redrawing the whole screen
+/// at each frame, like vtebench's dense_cells.
+fn colour_heavy() -> Vec<u8> {
+    let mut stream = String::new();
+    let mut frame = 0usize;
+    while stream.len() < STREAM_SIZE {
+        stream.push_str("\x1b[H");
+        for row in 0..ROWS {
+            for col in 0..COLS {
+                let index = frame + row * COLS + col;
+                match index % 3 {
+                    0 => write!(stream, "\x1b[38;5;{}m", index % 256),
+                    1 => write!(
+                        stream,
+                        "\x1b[38;2;{};{};{}m",
+                        index % 256,
+                        (index / 7) % 256,
+                        (index / 13) % 256
+                    ),
+                    _ => write!(stream, "\x1b[1;4;48;5;{}m", (index / 3) % 256),
+                }
+                .unwrap();
+                stream.push(char::from(b'A' + (index % 26) as u8));
+                stream.push_str("\x1b[0m");
+            }
+        }
+        frame += 1;
+    }
+    stream.into_bytes()
+}
+
+fn streams() -> Vec<(String, Vec<u8>)> {
+    let mut streams = vec![
+        (String::from("dense_scrolling"), dense_scrolling()),
+        (String::from("unicode"), unicode()),
+        (String::from("colour_heavy"), colour_heavy()),
+    ];
+
+    if let Ok(path) = std::env::var("RAVEN_BENCH_RECORDING") {
+        match std::fs::read(&path) {
+            Ok(recording) => streams.push((String::from("recording"), recording)),
+            Err(e) => eprintln!("Unable to read recording {}.\n{}", path, e),
+        }
+    }
+
+    streams
+}
+
+/// Parses the stream read by chunks, batching the actions like
+/// `PtyReadThread` does.
+fn parse(stream: &[u8]) -> Vec<Action> {
+    let mut parser = Parser::new();
+    let mut actions = vec![];
+    for chunk in stream.chunks(READ_SIZE) {
+        parser.parse(chunk, |action| action.append_to(&mut actions));
+    }
+    actions
+}
+
+fn new_terminal() -> Terminal {
+    Terminal::new(
+        TerminalSize {
+            rows: ROWS,
+            cols: COLS,
+            pixel_width: 0,
+            pixel_height: 0,
+            dpi: 1,
+        },
+        Arc::new(BenchConfig),
+        "Raven",
+        env!("CARGO_PKG_VERSION"),
+        Box::new(std::io::sink()),
+    )
+}
+
+/// Visible lines once the whole stream is performed, all of them sent.
+fn visible_lines(terminal: &Terminal) -> Vec<LineElement> {
+    let (lines, _, _) = render_terminal(terminal, 0, &mut RenderedRows::default());
+    lines.into_iter().map(|(_, line)| line).collect()
+}
+
+fn bench_pipeline(c: &mut Criterion) {
+    let font = FontConfig::default();
+    fonts::load_fonts(&font);
+    let font_collection = fonts::font_collection();
+    let settings = LayoutSettings {
+        cell_size: get_cell_size(&font, FONT_SIZE, None),
+        font,
+        font_size: FONT_SIZE,
+        line_height: None,
+    };
+
+    for (name, stream) in streams() {
+        let mut group = c.benchmark_group(&name);
+
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+        group.bench_function("parse", |b| b.iter(|| parse(&stream)));
+
+        let actions = parse(&stream);
+        group.bench_function("perform_actions", |b| {
+            b.iter_batched(
+                || (new_terminal(), actions.clone()),
+                |(mut terminal, actions)| {
+                    terminal.perform_actions(actions);
+                    terminal
+                },
+                BatchSize::LargeInput,
+            )
+        });
+
+        // The stages below handle a single frame
+        let mut terminal = new_terminal();
+        terminal.perform_actions(actions);
+        let lines = visible_lines(&terminal);
+        group.throughput(Throughput::Elements(lines.len() as u64));
+
+        group.bench_function("render_terminal", |b| {
+            b.iter_batched_ref(
+                RenderedRows::default,
+                |rendered_rows| render_terminal(&terminal, 0, rendered_rows),
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_function("clusters", |b| {
+            b.iter(|| {
+                lines
+                    .iter()
+                    .map(|line| line.clusters().len())
+                    .sum::<usize>()
+            })
+        });
+
+        group.bench_function("layout_lines", |b| {
+            b.iter_batched_ref(
+                LineLayoutCache::default,
+                |line_layouts| line_layouts.layout_lines(&lines, &settings, &font_collection, 0),
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_pipeline);
+criterion_main!(benches);
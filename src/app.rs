@@ -0,0 +1,390 @@
+use std::rc::Rc;
+
+use freya::prelude::*;
+use wezterm_term::{KeyCode, KeyModifiers};
+
+use crate::{
+    cli::CliArgs,
+    clipboard::TerminalClipboard,
+    components::{ContentArea, Sidebar},
+    config::TerminalConfig,
+    fonts,
+    hooks::{use_window_focus, UseTerminal},
+    notifier::{create_notifier, Notifier},
+    pane::PaneId,
+    state::{AppState, CloseRequest, PendingClose},
+};
+
+const MIN_SIDEBAR_WIDTH: f32 = 150.;
+const MAX_SIDEBAR_WIDTH: f32 = 600.;
+// Width of the edge of the sidebar that is dragged to resize it
+const SIDEBAR_HANDLE_WIDTH: f32 = 4.;
+
+/// Options the app is launched with, available through `use_context`.
+#[derive(Clone)]
+struct AppOptions {
+    config: TerminalConfig,
+    cli_args: CliArgs,
+}
+
+/// Opens the window and runs the app until it quits.
+pub fn launch(config: TerminalConfig, cli_args: CliArgs) {
+    let title = cli_args.title.clone().unwrap_or(String::from("Raven"));
+    let class = cli_args.class.clone();
+    let font_family = Box::leak(config.font.family.clone().into_boxed_str());
+    let decorations = config.window.decorations;
+
+    let mut launch_config = LaunchConfig::<AppOptions>::new();
+    for (family, data) in fonts::load_fonts(&config.font) {
+        launch_config = launch_config.with_font(family, data);
+    }
+
+    launch_cfg(
+        App,
+        launch_config
+            .with_state(AppOptions { config, cli_args })
+            .with_title(Box::leak(title.into_boxed_str()))
+            .with_window_builder(move |builder| with_window_class(builder, class))
+            .with_decorations(decorations)
+            .with_width(900.)
+            .with_height(600.)
+            .with_transparency(true)
+            .without_default_fonts()
+            .with_default_font(font_family),
+    );
+}
+
+/// Sets the X11 WM_CLASS (or the Wayland app id) of the window.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn with_window_class(
+    builder: winit::window::WindowAttributes,
+    class: Option<String>,
+) -> winit::window::WindowAttributes {
+    use winit::platform::x11::WindowAttributesExtX11;
+
+    match class {
+        // Both X11 and Wayland read the same attribute
+        Some(class) => builder.with_name(class.clone(), class),
+        None => builder,
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+fn with_window_class(
+    builder: winit::window::WindowAttributes,
+    _class: Option<String>,
+) -> winit::window::WindowAttributes {
+    builder
+}
+
+#[component]
+#[allow(non_snake_case)]
+fn App() -> Element {
+    let options = use_context::<AppOptions>();
+    let mut config = use_signal(|| options.config.clone());
+
+    let mut state = use_signal(|| {
+        let mut state = AppState::new();
+        let config = config.read();
+        let cli_args = &options.cli_args;
+        let pane = state.new_pane(
+            &config,
+            Some(&cli_args.launch_profile()),
+            config.working_directory.clone(),
+        );
+        if cli_args.title.is_some() {
+            state.rename_pane(pane.id, cli_args.title.clone());
+        }
+        state.set_active_pane(pane.id);
+        state
+    });
+    let active_pane = use_memo(move || state.read().active_pane());
+    let mut focus_manager = use_focus();
+    let platform = use_platform();
+
+    let mut renaming_pane = use_signal::<Option<PaneId>>(|| None);
+    let mut rename_value = use_signal(String::new);
+    let mut pending_close = use_signal::<Option<PendingClose>>(|| None);
+
+    let mut sidebar_visible = use_signal(|| config.read().window.sidebar);
+    let mut sidebar_width = use_signal(|| {
+        let width = config.read().window.sidebar_width;
+        width.clamp(MIN_SIDEBAR_WIDTH, MAX_SIDEBAR_WIDTH)
+    });
+    let mut resizing_sidebar = use_signal(|| false);
+
+    // Closes right away unless programs running in the panes would be killed
+    let mut request_close = move |request: CloseRequest| {
+        let programs = state
+            .read()
+            .programs_to_confirm(&request, &config.read().confirm_close);
+        if programs.is_empty() {
+            state.write().close(&request);
+        } else {
+            // The question is asked in the sidebar
+            sidebar_visible.set(true);
+            pending_close.set(Some(PendingClose { request, programs }));
+        }
+    };
+
+    // Closing the last pane quits
+    use_effect(move || {
+        if state.read().panes().is_empty() {
+            std::process::exit(0);
+        }
+    });
+
+    // Keep the window title in sync with the active pane
+    use_effect(move || {
+        let Some(pane) = state.read().active_pane() else {
+            return;
+        };
+        let title = pane.title();
+        platform.with_window(move |window| window.set_title(&title));
+    });
+
+    let mut clipboard = use_context_provider(|| {
+        Signal::new(TerminalClipboard::new(config.read().image_paste_mode))
+    });
+
+    use_context_provider::<Rc<dyn Notifier>>(|| {
+        create_notifier(config.read().notifications.notifier).into()
+    });
+
+    let window_focused = use_window_focus();
+
+    let onkeydown = move |e: KeyboardEvent| {
+        focus_manager.prevent_navigation();
+
+        // Keys answer the question while closing
+        if let Some(pending) = pending_close() {
+            let confirmed = match &e.key {
+                Key::Character(ch) if ch.eq_ignore_ascii_case("y") => Some(true),
+                Key::Character(ch) if ch.eq_ignore_ascii_case("n") => Some(false),
+                Key::Escape => Some(false),
+                _ => None,
+            };
+            if let Some(confirmed) = confirmed {
+                pending_close.set(None);
+                if confirmed {
+                    state.write().close(&pending.request);
+                }
+            }
+            return;
+        }
+
+        // Keys are typed into the pane title while renaming
+        if let Some(pane_id) = renaming_pane() {
+            match &e.key {
+                Key::Enter => {
+                    state.write().rename_pane(pane_id, Some(rename_value()));
+                    renaming_pane.set(None);
+                }
+                Key::Escape => renaming_pane.set(None),
+                Key::Backspace => {
+                    rename_value.write().pop();
+                }
+                Key::Character(ch) => rename_value.write().push_str(ch),
+                _ => {}
+            }
+            return;
+        }
+
+        let Some(pane) = active_pane.read().clone() else {
+            return;
+        };
+
+        // Handle opening a new pane in the directory of the active one
+        let is_new_pane_key = matches!(&e.key, Key::Character(ch) if ch.eq_ignore_ascii_case("t"));
+        if e.modifiers.ctrl() && e.modifiers.shift() && is_new_pane_key {
+            state.write().open_pane(&config.read(), None);
+            return;
+        }
+
+        // Handle closing the active pane
+        let is_close_key = matches!(&e.key, Key::Character(ch) if ch.eq_ignore_ascii_case("w"));
+        if e.modifiers.ctrl() && e.modifiers.shift() && is_close_key {
+            request_close(CloseRequest::Pane(pane.id));
+            return;
+        }
+
+        // Handle showing and hiding the sidebar
+        let is_sidebar_key = matches!(&e.key, Key::Character(ch) if ch.eq_ignore_ascii_case("b"));
+        if e.modifiers.ctrl() && e.modifiers.shift() && is_sidebar_key {
+            let is_visible = sidebar_visible();
+            sidebar_visible.set(!is_visible);
+            return;
+        }
+
+        // Handle quitting
+        let is_quit_key = matches!(&e.key, Key::Character(ch) if ch.eq_ignore_ascii_case("q"));
+        if e.modifiers.ctrl() && e.modifiers.shift() && is_quit_key {
+            request_close(CloseRequest::App);
+            return;
+        }
+
+        // Handle renaming the active pane
+        let is_rename_key = matches!(&e.key, Key::Character(ch) if ch.eq_ignore_ascii_case("r"));
+        if e.modifiers.ctrl() && e.modifiers.shift() && is_rename_key {
+            rename_value.set(pane.title());
+            renaming_pane.set(Some(pane.id));
+            sidebar_visible.set(true);
+            return;
+        }
+
+        let terminal = UseTerminal::new(pane);
+
+        // Handle jumping between prompts
+        if e.modifiers.ctrl() && e.modifiers.shift() {
+            let direction = match &e.key {
+                Key::ArrowUp => Some(-1),
+                Key::ArrowDown => Some(1),
+                _ => None,
+            };
+
+            if let Some(direction) = direction {
+                terminal.scroll_to_prompt(direction);
+                return;
+            }
+        }
+
+        let mods = if e.modifiers.alt() {
+            KeyModifiers::ALT
+        } else if e.modifiers.shift() {
+            KeyModifiers::SHIFT
+        } else if e.modifiers.meta() {
+            KeyModifiers::SUPER
+        } else if e.modifiers.ctrl() {
+            KeyModifiers::CTRL
+        } else {
+            KeyModifiers::NONE
+        };
+
+        match &e.key {
+            Key::Character(ch) => {
+                let meta_or_ctrl = if cfg!(target_os = "macos") {
+                    e.modifiers.meta()
+                } else {
+                    e.modifiers.ctrl()
+                };
+
+                // Handle pasting content to terminal
+                if ch == "v" && meta_or_ctrl {
+                    if let Some(content) = clipboard.write().paste_content() {
+                        terminal.paste(content);
+                    }
+                    return;
+                };
+
+                // Handle copying selection content from terminal
+                if ch == "c" && meta_or_ctrl {
+                    terminal.copy_selection();
+                    return;
+                };
+
+                // Handle zoom in
+                if (ch == "+" || ch == "=") && (meta_or_ctrl || e.modifiers.ctrl()) {
+                    let new_font_size = config.read().font_size + 1.;
+                    config.write().set_font_size(new_font_size);
+                    return;
+                }
+
+                // Handle zoom out
+                if (ch == "-" || ch == "_") && (meta_or_ctrl || e.modifiers.ctrl()) {
+                    let new_font_size = config.read().font_size - 1.;
+                    config.write().set_font_size(new_font_size);
+                    return;
+                }
+
+                // Handle typing regular keys
+                let key_code = KeyCode::Char(ch.chars().next().unwrap());
+                terminal.key_down(key_code, mods);
+            }
+            key => {
+                let recognised_key = match key {
+                    Key::Enter => Some(KeyCode::Enter),
+                    Key::Backspace => Some(KeyCode::Backspace),
+                    Key::Tab => Some(KeyCode::Tab),
+                    Key::ArrowDown => Some(KeyCode::DownArrow),
+                    Key::ArrowLeft => Some(KeyCode::LeftArrow),
+                    Key::ArrowRight => Some(KeyCode::RightArrow),
+                    Key::ArrowUp => Some(KeyCode::UpArrow),
+                    Key::Shift => Some(KeyCode::Shift),
+                    Key::Control => Some(KeyCode::Control),
+                    Key::Escape => Some(KeyCode::Escape),
+                    Key::Super => Some(KeyCode::Super),
+                    key => {
+                        println!("Unrecognised key: {}", key);
+                        None
+                    }
+                };
+
+                if let Some(key_code) = recognised_key {
+                    terminal.key_down(key_code, mods);
+                }
+            }
+        };
+    };
+
+    // Resize the sidebar while its edge is dragged
+    let onmouseover = move |e: MouseEvent| {
+        if resizing_sidebar() {
+            let width = e.get_screen_coordinates().x as f32;
+            sidebar_width.set(width.clamp(MIN_SIDEBAR_WIDTH, MAX_SIDEBAR_WIDTH));
+        }
+    };
+
+    let content_width = if sidebar_visible() {
+        format!("calc(100% - {})", sidebar_width() + SIDEBAR_HANDLE_WIDTH)
+    } else {
+        String::from("100%")
+    };
+
+    rsx!(
+        rect {
+            width: "100%",
+            height: "100%",
+            background: "rgb(17, 21, 28)",
+            color: "rgb(86, 91, 120)",
+            direction: "horizontal",
+            font_size: "{config.read().font_size}",
+            onkeydown: onkeydown,
+            onmouseover: onmouseover,
+            onmouseup: move |_| resizing_sidebar.set(false),
+            if sidebar_visible() {
+                Sidebar {
+                    state: state,
+                    config: config,
+                    width: sidebar_width(),
+                    renaming_pane: renaming_pane,
+                    rename_value: rename_value,
+                    pending_close: pending_close
+                }
+
+                rect {
+                    width: "{SIDEBAR_HANDLE_WIDTH}",
+                    height: "100%",
+                    onmousedown: move |_| resizing_sidebar.set(true),
+                }
+            }
+
+            rect {
+                width: "{content_width}",
+                height: "100%",
+
+                // Every pane stays mounted so background panes keep handling
+                // their terminal events. Only the active one is displayed.
+                for pane in state.read().panes() {
+                    ContentArea {
+                        key: "{pane.id}",
+                        active: active_pane().map(|active| active.id) == Some(pane.id),
+                        pane: pane,
+                        config: config,
+                        state: state,
+                        window_focused: window_focused
+                    }
+                }
+            }
+        }
+    )
+}
@@ -0,0 +1,217 @@
+use std::path::PathBuf;
+
+use crate::config::LaunchProfile;
+
+pub const USAGE: &str = "\
+Usage: raven [OPTIONS] [-e PROGRAM [ARGS]...]
+
+Options:
+  -e, --command PROGRAM [ARGS]...  Run PROGRAM instead of the shell. Must be last
+      --cwd DIR                    Start in DIR
+      --config FILE                Read the config from FILE
+      --title TITLE                Set the window title
+      --class CLASS                Set the window class (app id on Wayland)
+      --hold                       Keep the pane open after the program exits
+      --font-size SIZE             Override the font size
+  -h, --help                       Print this help
+  -V, --version                    Print the version
+";
+
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct CliArgs {
+    // Program and its arguments to run in the first pane
+    pub command: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub title: Option<String>,
+    pub class: Option<String>,
+    pub hold: bool,
+    pub font_size: Option<f32>,
+}
+
+/// What the command line asks for.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParseOutcome {
+    Run(CliArgs),
+    // Print the usage
+    Help,
+    // Print the version
+    Version,
+}
+
+impl CliArgs {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<ParseOutcome, String> {
+        let mut cli_args = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            // Support both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+
+            let mut value = |name: &str| {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            // Switches don't take a value, don't silently drop one
+            if inline_value.is_some() && matches!(flag.as_str(), "--hold" | "--help" | "--version")
+            {
+                return Err(format!("unexpected value for {}", flag));
+            }
+
+            match flag.as_str() {
+                "-e" | "--command" => {
+                    cli_args.command = inline_value.into_iter().chain(args.by_ref()).collect();
+                    if cli_args.command.is_empty() {
+                        return Err(format!("missing program for {}", flag));
+                    }
+                }
+                "--cwd" => cli_args.cwd = Some(PathBuf::from(value("--cwd")?)),
+                "--config" => cli_args.config = Some(PathBuf::from(value("--config")?)),
+                "--title" => cli_args.title = Some(value("--title")?),
+                "--class" => cli_args.class = Some(value("--class")?),
+                "--hold" => cli_args.hold = true,
+                "--font-size" => {
+                    let font_size = value("--font-size")?;
+                    // The cells would have no size, or an infinite one
+                    let parsed = font_size
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|size| size.is_finite() && *size > 0.);
+                    match parsed {
+                        Some(size) => cli_args.font_size = Some(size),
+                        None => return Err(format!("invalid font size: {}", font_size)),
+                    }
+                }
+                "-h" | "--help" => return Ok(ParseOutcome::Help),
+                "-V" | "--version" => return Ok(ParseOutcome::Version),
+                _ => return Err(format!("unexpected argument: {}", flag)),
+            }
+        }
+
+        Ok(ParseOutcome::Run(cli_args))
+    }
+
+    /// Profile of the first pane.
+    pub fn launch_profile(&self) -> LaunchProfile {
+        let (program, args) = match self.command.split_first() {
+            Some((program, args)) => (Some(program.clone()), args.to_vec()),
+            None => (None, Vec::new()),
+        };
+
+        LaunchProfile {
+            name: program.clone().unwrap_or_default(),
+            program,
+            args,
+            working_directory: self.cwd.clone(),
+            hold: self.hold,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_outcome(args: &[&str]) -> Result<ParseOutcome, String> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse(args: &[&str]) -> Result<CliArgs, String> {
+        match parse_outcome(args)? {
+            ParseOutcome::Run(cli_args) => Ok(cli_args),
+            outcome => panic!("expected arguments to run with, got {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn command_takes_the_remaining_arguments() {
+        let cli_args = parse(&["--title", "logs", "-e", "tail", "-f", "--cwd", "log"]).unwrap();
+
+        assert_eq!(cli_args.title.as_deref(), Some("logs"));
+        assert_eq!(cli_args.command, ["tail", "-f", "--cwd", "log"]);
+        assert_eq!(cli_args.cwd, None);
+    }
+
+    #[test]
+    fn command_requires_a_program() {
+        assert!(parse(&["-e"]).is_err());
+        assert!(parse(&["--command"]).is_err());
+    }
+
+    #[test]
+    fn flags_take_inline_values() {
+        let cli_args = parse(&["--cwd=/tmp", "--title=a=b", "--font-size=16.5"]).unwrap();
+
+        assert_eq!(cli_args.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(cli_args.title.as_deref(), Some("a=b"));
+        assert_eq!(cli_args.font_size, Some(16.5));
+    }
+
+    #[test]
+    fn missing_value_is_an_error() {
+        assert_eq!(
+            parse(&["--config"]),
+            Err(String::from("missing value for --config"))
+        );
+    }
+
+    #[test]
+    fn invalid_font_size_is_an_error() {
+        assert_eq!(
+            parse(&["--font-size", "big"]),
+            Err(String::from("invalid font size: big"))
+        );
+    }
+
+    #[test]
+    fn font_size_must_be_positive_and_finite() {
+        for font_size in ["0", "-5", "inf", "NaN"] {
+            assert_eq!(
+                parse(&["--font-size", font_size]),
+                Err(format!("invalid font size: {}", font_size))
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_argument_is_an_error() {
+        assert_eq!(
+            parse(&["--fullscreen"]),
+            Err(String::from("unexpected argument: --fullscreen"))
+        );
+        assert!(parse(&["file.txt"]).is_err());
+    }
+
+    #[test]
+    fn help_and_version_stop_parsing() {
+        assert_eq!(
+            parse_outcome(&["--title", "x", "-h"]),
+            Ok(ParseOutcome::Help)
+        );
+        assert_eq!(
+            parse_outcome(&["--help", "--bogus"]),
+            Ok(ParseOutcome::Help)
+        );
+        assert_eq!(parse_outcome(&["-V"]), Ok(ParseOutcome::Version));
+        assert_eq!(parse_outcome(&["--version"]), Ok(ParseOutcome::Version));
+        assert!(parse_outcome(&["--version=1"]).is_err());
+    }
+
+    #[test]
+    fn hold_rejects_inline_values() {
+        assert!(parse(&["--hold"]).unwrap().hold);
+        assert_eq!(
+            parse(&["--hold=false"]),
+            Err(String::from("unexpected value for --hold"))
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::{io::Cursor, path::PathBuf};
+
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::utils::{percent_decode, shell_quote};
+
+/// Content read from a clipboard backend.
+#[derive(Clone)]
+pub enum ClipboardContent {
+    Text(String),
+    Image {
+        width: usize,
+        height: usize,
+        // RGBA pixels, row by row
+        bytes: Vec<u8>,
+    },
+}
+
+/// How to paste image content into the terminal.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImagePasteMode {
+    // Save the image as a PNG in the temp dir and paste its path
+    #[default]
+    Path,
+    // Paste the PNG encoded as base64
+    Base64,
+    // Don't paste images at all
+    Ignore,
+}
+
+pub trait ClipboardBackend {
+    fn get_content(&mut self) -> anyhow::Result<Option<ClipboardContent>>;
+    fn set_text(&mut self, text: String) -> anyhow::Result<()>;
+}
+
+/// Clipboard of the operating system.
+pub struct SystemClipboard(arboard::Clipboard);
+
+impl SystemClipboard {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self(arboard::Clipboard::new()?))
+    }
+}
+
+impl ClipboardBackend for SystemClipboard {
+    fn get_content(&mut self) -> anyhow::Result<Option<ClipboardContent>> {
+        match self.0.get_text() {
+            Ok(text) => return Ok(Some(ClipboardContent::Text(text))),
+            Err(arboard::Error::ContentNotAvailable) => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        match self.0.get_image() {
+            Ok(image) => Ok(Some(ClipboardContent::Image {
+                width: image.width,
+                height: image.height,
+                bytes: image.bytes.into_owned(),
+            })),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_text(&mut self, text: String) -> anyhow::Result<()> {
+        self.0.set_text(text)?;
+        Ok(())
+    }
+}
+
+/// Clipboard that only lives in memory. Used when the system clipboard is not
+/// available (headless, broken X forwarding...) and in tests.
+#[derive(Default)]
+pub struct MemoryClipboard {
+    content: Option<ClipboardContent>,
+}
+
+impl ClipboardBackend for MemoryClipboard {
+    fn get_content(&mut self) -> anyhow::Result<Option<ClipboardContent>> {
+        Ok(self.content.clone())
+    }
+
+    fn set_text(&mut self, text: String) -> anyhow::Result<()> {
+        self.content = Some(ClipboardContent::Text(text));
+        Ok(())
+    }
+}
+
+/// Clipboard used by the terminal. Never panics: failures are logged and
+/// treated as an empty clipboard.
+pub struct TerminalClipboard {
+    backend: Box<dyn ClipboardBackend>,
+    image_paste_mode: ImagePasteMode,
+}
+
+impl TerminalClipboard {
+    pub fn new(image_paste_mode: ImagePasteMode) -> Self {
+        let backend: Box<dyn ClipboardBackend> = match SystemClipboard::new() {
+            Ok(clipboard) => Box::new(clipboard),
+            Err(e) => {
+                log::warn!(
+                    "System clipboard is not available. Falling back to in-memory clipboard...\n{}",
+                    e
+                );
+                Box::new(MemoryClipboard::default())
+            }
+        };
+
+        Self::with_backend(backend, image_paste_mode)
+    }
+
+    pub fn with_backend(
+        backend: Box<dyn ClipboardBackend>,
+        image_paste_mode: ImagePasteMode,
+    ) -> Self {
+        Self {
+            backend,
+            image_paste_mode,
+        }
+    }
+
+    /// Text to send to the terminal when pasting, if any.
+    pub fn paste_content(&mut self) -> Option<String> {
+        let content = match self.backend.get_content() {
+            Ok(content) => content?,
+            Err(e) => {
+                log::warn!("Unable to read clipboard content.\n{}", e);
+                return None;
+            }
+        };
+
+        match content {
+            ClipboardContent::Text(text) => Some(file_uris_to_paths(&text).unwrap_or(text)),
+            ClipboardContent::Image {
+                width,
+                height,
+                bytes,
+            } => match self.image_paste_mode {
+                ImagePasteMode::Ignore => None,
+                mode => match paste_image(mode, width, height, bytes) {
+                    Ok(text) => Some(text),
+                    Err(e) => {
+                        log::warn!("Unable to paste image from clipboard.\n{}", e);
+                        None
+                    }
+                },
+            },
+        }
+    }
+
+    /// Raw text content of the clipboard, if any.
+    pub fn text(&mut self) -> Option<String> {
+        match self.backend.get_content() {
+            Ok(Some(ClipboardContent::Text(text))) => Some(text),
+            Ok(_) => None,
+            Err(e) => {
+                log::warn!("Unable to read clipboard content.\n{}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        if let Err(e) = self.backend.set_text(text) {
+            log::warn!("Unable to set clipboard content.\n{}", e);
+        }
+    }
+}
+
+/// Converts a `text/uri-list` style content (as copied from file managers)
+/// into space separated, shell quoted paths.
+fn file_uris_to_paths(text: &str) -> Option<String> {
+    let uris = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect::<Vec<_>>();
+
+    if uris.is_empty() {
+        return None;
+    }
+
+    let paths = uris
+        .into_iter()
+        .map(|uri| {
+            let path = uri.strip_prefix("file://")?;
+            // Strip the host part (usually empty or localhost)
+            let path = &path[path.find('/')?..];
+            let path = String::from_utf8_lossy(&percent_decode(path)).into_owned();
+            Some(shell_quote(&path))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(paths.join(" "))
+}
+
+fn paste_image(
+    mode: ImagePasteMode,
+    width: usize,
+    height: usize,
+    bytes: Vec<u8>,
+) -> anyhow::Result<String> {
+    let image = image::RgbaImage::from_raw(width as u32, height as u32, bytes)
+        .ok_or_else(|| anyhow::anyhow!("Invalid image data"))?;
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)?;
+
+    if mode == ImagePasteMode::Base64 {
+        return Ok(base64::engine::general_purpose::STANDARD.encode(png));
+    }
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis();
+    let path: PathBuf = std::env::temp_dir().join(format!("raven-clipboard-{}.png", timestamp));
+    std::fs::write(&path, png)?;
+
+    Ok(shell_quote(&path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clipboard(content: Option<ClipboardContent>, mode: ImagePasteMode) -> TerminalClipboard {
+        TerminalClipboard::with_backend(Box::new(MemoryClipboard { content }), mode)
+    }
+
+    fn text_clipboard(text: &str) -> TerminalClipboard {
+        clipboard(
+            Some(ClipboardContent::Text(text.to_string())),
+            ImagePasteMode::default(),
+        )
+    }
+
+    fn image_clipboard(mode: ImagePasteMode) -> TerminalClipboard {
+        let image = ClipboardContent::Image {
+            width: 2,
+            height: 1,
+            bytes: vec![255, 0, 0, 255, 0, 0, 255, 255],
+        };
+        clipboard(Some(image), mode)
+    }
+
+    #[test]
+    fn pastes_text_as_is() {
+        let mut clipboard = text_clipboard("echo 'hello'\n");
+        assert_eq!(clipboard.paste_content().as_deref(), Some("echo 'hello'\n"));
+    }
+
+    #[test]
+    fn pastes_nothing_from_an_empty_clipboard() {
+        let mut clipboard = clipboard(None, ImagePasteMode::default());
+        assert_eq!(clipboard.paste_content(), None);
+        assert_eq!(clipboard.text(), None);
+    }
+
+    #[test]
+    fn keeps_the_text_it_is_given() {
+        let mut clipboard = clipboard(None, ImagePasteMode::default());
+        clipboard.set_text(String::from("copied"));
+        assert_eq!(clipboard.text().as_deref(), Some("copied"));
+    }
+
+    #[test]
+    fn pastes_file_uris_as_quoted_paths() {
+        let mut clipboard = text_clipboard(
+            "# copied from the file manager\r\n\
+             file:///home/me/My%20Notes.txt\r\n\
+             file://localhost/tmp/a.png\r\n",
+        );
+        assert_eq!(
+            clipboard.paste_content().as_deref(),
+            Some("'/home/me/My Notes.txt' /tmp/a.png")
+        );
+    }
+
+    #[test]
+    fn only_converts_lists_of_file_uris() {
+        assert_eq!(file_uris_to_paths("https://example.com/a%20b"), None);
+        assert_eq!(
+            file_uris_to_paths("file:///tmp/a\nhttps://example.com"),
+            None
+        );
+        assert_eq!(file_uris_to_paths("\n# comment\n"), None);
+    }
+
+    #[test]
+    fn ignores_images_when_asked_to() {
+        let mut clipboard = image_clipboard(ImagePasteMode::Ignore);
+        assert_eq!(clipboard.paste_content(), None);
+    }
+
+    #[test]
+    fn pastes_images_as_base64_png() {
+        let mut clipboard = image_clipboard(ImagePasteMode::Base64);
+        let pasted = clipboard.paste_content().unwrap();
+
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(pasted)
+            .unwrap();
+        let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.into_raw(), [255, 0, 0, 255, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn pastes_nothing_for_invalid_images() {
+        let image = ClipboardContent::Image {
+            width: 4,
+            height: 4,
+            bytes: vec![0; 3],
+        };
+        let mut clipboard = clipboard(Some(image), ImagePasteMode::Base64);
+        assert_eq!(clipboard.paste_content(), None);
+    }
+}
@@ -1,18 +1,41 @@
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Duration;
 
-use arboard::Clipboard;
 use freya::prelude::*;
-use skia_safe::textlayout::{ParagraphBuilder, ParagraphStyle, TextStyle};
-use skia_safe::{Color, Paint};
-
-use crate::config::TerminalConfig;
+use skia_safe::{Color, Paint, PaintStyle};
+use termwiz::color::RgbColor;
+use termwiz::surface::{CursorShape, CursorVisibility};
+use wezterm_term::CursorPosition;
+use winit::window::UserAttentionType;
+
+use crate::clipboard::TerminalClipboard;
+use crate::config::{CursorStyle, TerminalConfig};
 use crate::hooks::use_debounce;
+use crate::layout::{LayoutSettings, LineLayoutCache};
+use crate::notifier::Notifier;
 use crate::selection::Selection;
-use crate::utils::get_cell_size;
+use crate::state::AppState;
+use crate::utils::{get_cell_size, run_command_detached};
 use crate::{
-    hooks::use_terminal, pane::Pane, rendering::LineElement, terminal_loop::TerminalEvent,
+    hooks::use_terminal,
+    pane::Pane,
+    rendering::{CommandMark, LineElement},
+    terminal_loop::{TerminalEvent, UserEvent},
 };
 
+const BELL_FLASH_DURATION: Duration = Duration::from_millis(100);
+// Rapid blinking text toggles every tick, slow blinking text every two ticks
+const TEXT_BLINK_INTERVAL: Duration = Duration::from_millis(250);
+// How often to check whether the cursor should start blinking when it doesn't
+const CURSOR_IDLE_INTERVAL: Duration = Duration::from_millis(500);
+const CURSOR_BAR_WIDTH: f32 = 2.;
+const CURSOR_UNDERLINE_HEIGHT: f32 = 2.;
+const GUTTER_MARK_WIDTH: f32 = 3.;
+// Distance of the command marks from the terminal content
+const GUTTER_MARK_OFFSET: f32 = 20.;
+
 #[component]
 #[allow(non_snake_case)]
 pub fn ContentArea(
@@ -20,18 +43,31 @@ pub fn ContentArea(
     pane: Arc<Pane>,
     // Terminal Config
     config: Signal<TerminalConfig>,
+    // App state, updated with the pane info reported by the terminal
+    mut state: Signal<AppState>,
+    // Whether the pane is the one being displayed
+    active: bool,
+    // Whether the window has the focus
+    window_focused: Signal<bool, SyncStorage>,
 ) -> Element {
     let mut rendered_lines = use_signal_sync::<Vec<LineElement>>(|| vec![]);
-    let mut rendered_cursor = use_signal_sync::<(usize, usize)>(|| (0, 0));
+    let mut rendered_cursor = use_signal_sync(CursorPosition::default);
+    let mut cursor_blink_on = use_signal_sync(|| true);
     let mut rendered_scroll_top = use_signal_sync::<usize>(|| 0);
     let mut rendered_selection = use_signal_sync::<Option<Selection>>(|| None);
     let mut rendered_terminal_size = use_signal_sync::<(usize, usize)>(|| (0, 0));
+    let mut rendered_command_marks = use_signal_sync::<Vec<CommandMark>>(|| vec![]);
+    let mut bell_flash = use_signal_sync(|| false);
+    let mut has_blinking_text = use_signal_sync(|| false);
+    let mut text_blink_tick = use_signal_sync::<usize>(|| 0);
+    // Error the terminal failed to start or stopped on
+    let mut terminal_error = use_signal_sync::<Option<String>>(|| None);
+    let platform = use_platform();
     let terminal = use_terminal(pane.clone());
+    let mut clipboard = use_context::<Signal<TerminalClipboard>>();
+    let notifier = use_context::<Rc<dyn Notifier>>();
 
-    let padding_top = 50.;
-    let padding_right = 50.;
-    let padding_bottom = 40.;
-    let padding_left = 100.;
+    let padding = config.read().window.padding;
 
     let (node_ref, size) = use_node_signal();
 
@@ -47,7 +83,7 @@ pub fn ContentArea(
 
     let cell_size = use_memo(move || {
         let config = config.read();
-        get_cell_size(config.font_size, config.line_height)
+        get_cell_size(&config.font, config.font_size, config.line_height)
     });
 
     let onwheel = use_debounce(std::time::Duration::from_millis(15), {
@@ -102,32 +138,220 @@ pub fn ContentArea(
         }
     });
 
+    // Only redraw periodically when there is blinking text on screen
+    use_hook(move || {
+        spawn(async move {
+            loop {
+                tokio::time::sleep(TEXT_BLINK_INTERVAL).await;
+                if *has_blinking_text.peek() {
+                    *text_blink_tick.write() += 1;
+                }
+            }
+        });
+    });
+
+    let cursor_color = use_memo(move || {
+        config
+            .read()
+            .cursor
+            .color
+            .as_deref()
+            .and_then(RgbColor::from_named_or_rgb_string)
+            .map(|color| color.to_tuple_rgb8())
+    });
+
+    use_hook(move || {
+        spawn(async move {
+            loop {
+                let cursor_config = config.peek().cursor.clone();
+                let is_blinking = match rendered_cursor.peek().shape {
+                    CursorShape::Default => cursor_config.blinking,
+                    shape => shape.is_blinking(),
+                };
+
+                if cursor_config.blink_interval == 0 || !is_blinking || !*window_focused.peek() {
+                    if !*cursor_blink_on.peek() {
+                        cursor_blink_on.set(true);
+                    }
+                    tokio::time::sleep(CURSOR_IDLE_INTERVAL).await;
+                    continue;
+                }
+
+                tokio::time::sleep(Duration::from_millis(cursor_config.blink_interval)).await;
+                let blink_on = *cursor_blink_on.peek();
+                cursor_blink_on.set(!blink_on);
+            }
+        });
+    });
+
+    // Redraw at most as often as the display refreshes. The rate is read once
+    // the pane is mounted: moving the window to another display keeps it.
+    use_hook({
+        let user_event_tx = pane.terminal_bridge().user_event_sender().clone();
+        move || {
+            let max_fps = config.peek().max_fps;
+            platform.with_window(move |window| {
+                let refresh_rate = window
+                    .current_monitor()
+                    .and_then(|monitor| monitor.refresh_rate_millihertz())
+                    .map(|millihertz| millihertz / 1000);
+                let frame_rate = match (refresh_rate, max_fps) {
+                    (Some(refresh_rate), Some(max_fps)) => Some(refresh_rate.min(max_fps)),
+                    (refresh_rate, max_fps) => refresh_rate.or(max_fps),
+                };
+                if let Some(frame_rate) = frame_rate {
+                    user_event_tx.send(UserEvent::SetFrameRate(frame_rate)).ok();
+                }
+            });
+        }
+    });
+
     use_hook({
         let terminal_event_rx = pane.terminal_bridge().terminal_event_receiver().clone();
+        let terminal = terminal.clone();
         move || {
             spawn(async move {
                 while let Ok(event) = terminal_event_rx.recv_async().await {
                     match event {
                         TerminalEvent::Redraw {
                             lines,
+                            line_count,
                             cursor,
                             scroll_top,
                             selection,
                             terminal_visible_size,
+                            command_marks,
                         } => {
-                            *rendered_lines.write() = lines;
-                            *rendered_cursor.write() = (cursor.x, cursor.y as usize);
+                            {
+                                // Only the lines that changed are sent, patch them in place
+                                let mut rendered_lines = rendered_lines.write();
+                                rendered_lines.truncate(line_count);
+                                for (row, line) in lines {
+                                    match rendered_lines.get_mut(row) {
+                                        Some(rendered_line) => *rendered_line = line,
+                                        None => rendered_lines.push(line),
+                                    }
+                                }
+                                has_blinking_text
+                                    .set(rendered_lines.iter().any(LineElement::has_blinking_text));
+                            }
+                            *rendered_cursor.write() = cursor;
+                            // Keep the cursor shown while the content changes
+                            if !*cursor_blink_on.peek() {
+                                cursor_blink_on.set(true);
+                            }
                             *rendered_scroll_top.write() = scroll_top;
                             *rendered_selection.write() = selection;
                             *rendered_terminal_size.write() = terminal_visible_size;
+                            *rendered_command_marks.write() = command_marks;
+                            pane.terminal_bridge().frame_handled();
+                        }
+                        TerminalEvent::TitleChanged(title) => {
+                            state.write().set_pane_title(pane.id, title);
+                        }
+                        TerminalEvent::CwdChanged(cwd) => {
+                            state.write().set_pane_cwd(pane.id, cwd);
+                        }
+                        TerminalEvent::Bell => {
+                            let bell = config.read().bell.clone();
+
+                            if bell.badge {
+                                state.write().ring_pane_bell(pane.id);
+                            }
+
+                            if bell.visual {
+                                bell_flash.set(true);
+                                spawn(async move {
+                                    tokio::time::sleep(BELL_FLASH_DURATION).await;
+                                    bell_flash.set(false);
+                                });
+                            }
+
+                            if bell.urgency_hint {
+                                platform.with_window(|window| {
+                                    if !window.has_focus() {
+                                        window.request_user_attention(Some(
+                                            UserAttentionType::Informational,
+                                        ));
+                                    }
+                                });
+                            }
+
+                            if let Some((program, args)) = bell
+                                .audible_command
+                                .as_ref()
+                                .and_then(|command| command.split_first())
+                            {
+                                run_command_detached(program, args);
+                            }
+                        }
+                        TerminalEvent::Notification { title, body } => {
+                            let title = title.unwrap_or_else(|| pane.title());
+                            notifier.notify(&title, &body);
+                        }
+                        TerminalEvent::CommandFinished {
+                            duration,
+                            exit_status,
+                        } => {
+                            let Some(threshold) =
+                                config.read().notifications.command_finished_threshold
+                            else {
+                                continue;
+                            };
+
+                            let is_pane_focused =
+                                window_focused() && state.read().active_pane_id() == Some(pane.id);
+
+                            if is_pane_focused || duration.as_secs() < threshold {
+                                continue;
+                            }
+
+                            let body = match exit_status {
+                                Some(code) if code != 0 => format!(
+                                    "Command failed with exit code {} after {}s",
+                                    code,
+                                    duration.as_secs()
+                                ),
+                                _ => format!("Command finished after {}s", duration.as_secs()),
+                            };
+                            notifier.notify(&pane.title(), &body);
+                        }
+                        TerminalEvent::Error(error) => {
+                            log::error!("Terminal of pane {} failed.\n{}", pane.id, error);
+                            terminal_error.set(Some(error.to_string()));
+                        }
+                        TerminalEvent::ForegroundCommandChanged(command) => {
+                            state.write().set_pane_foreground_command(pane.id, command);
+                        }
+                        TerminalEvent::Activity => {
+                            // Writing the state renders the sidebar again, only do it
+                            // when the indicator shows up
+                            let is_background = state.peek().active_pane_id() != Some(pane.id);
+                            if is_background && !pane.has_activity() {
+                                state.write().mark_pane_activity(pane.id);
+                            }
+                        }
+                        TerminalEvent::ProcessExited { exit_code } => {
+                            log::info!(
+                                "Program of pane {} exited with code {:?}",
+                                pane.id,
+                                exit_code
+                            );
+                            state.write().set_pane_exit_code(pane.id, exit_code);
+                        }
+                        TerminalEvent::ProcessRestarted => {
+                            state.write().set_pane_exit_code(pane.id, None);
                         }
                         TerminalEvent::Exit => {
-                            pane.close();
+                            state.write().close_pane(pane.id);
                             break;
                         }
                         TerminalEvent::SetClipboardContent(content) => {
-                            let mut clipboard = Clipboard::new().unwrap();
-                            clipboard.set_text(content).ok();
+                            clipboard.write().set_text(content);
+                        }
+                        TerminalEvent::RequestClipboardContent => {
+                            let content = clipboard.write().text();
+                            terminal.send_event(UserEvent::ClipboardContent(content));
                         }
                     }
                 }
@@ -135,35 +359,53 @@ pub fn ContentArea(
         }
     });
 
+    let line_layouts = use_hook(|| Rc::new(RefCell::new(LineLayoutCache::default())));
+
     let canvas = use_canvas(move || {
         let cursor = rendered_cursor();
-        let font_size = font_size();
-        let line_height = line_height();
-        let lines = rendered_lines();
+        // Subscribes to the lines, they're only borrowed when painting
+        let has_lines = !rendered_lines.read().is_empty();
         let cell_size = cell_size();
         let selection = rendered_selection();
         let terminal_size = rendered_terminal_size();
         let scroll_top = rendered_scroll_top();
+        let bell_flash = bell_flash();
+        let text_blink_tick = text_blink_tick();
+        let cursor_blink_on = cursor_blink_on();
+        let cursor_color = cursor_color();
+        let layout_settings = LayoutSettings {
+            font: config.read().font.clone(),
+            font_size: font_size(),
+            line_height: line_height(),
+            cell_size,
+        };
+        let line_layouts = line_layouts.clone();
+        // Only the active pane is displayed, so it's focused with the window
+        let is_focused = window_focused();
+        let cursor_style = match cursor.shape {
+            CursorShape::Default => config.read().cursor.style,
+            CursorShape::BlinkingBlock | CursorShape::SteadyBlock => CursorStyle::Block,
+            CursorShape::BlinkingBar | CursorShape::SteadyBar => CursorStyle::Bar,
+            CursorShape::BlinkingUnderline | CursorShape::SteadyUnderline => CursorStyle::Underline,
+        };
+        let is_cursor_visible = cursor.visibility == CursorVisibility::Visible
+            && scroll_top == 0
+            && (cursor_blink_on || !is_focused);
         Box::new(move |canvas, font_collection, region, scale_factor| {
-            if lines.len() == 0 {
+            if !has_lines {
                 return;
             }
+            let lines = rendered_lines.peek();
 
             canvas.translate((region.min_x(), region.min_y()));
             canvas.scale((scale_factor, scale_factor));
 
-            let mut style = ParagraphStyle::default();
-            let mut text_style = TextStyle::default();
-            text_style.set_font_size(font_size);
-            text_style.set_font_families(&["jetbrains mono"]);
-
-            if let Some(line_height) = line_height {
-                text_style.set_height_override(true);
-                text_style.set_height(line_height);
-            }
-
-            style.set_text_style(&text_style);
-            let mut paragraph_builder = ParagraphBuilder::new(&style, font_collection.clone());
+            let layouts = line_layouts.borrow_mut().layout_lines(
+                &lines,
+                &layout_settings,
+                font_collection,
+                text_blink_tick,
+            );
 
             let mut paint = Paint::default();
             paint.set_anti_alias(true);
@@ -171,59 +413,44 @@ pub fn ContentArea(
             let mut y = 0.;
             let mut cursor_y = y;
 
-            for (line_index, line) in lines.iter().enumerate() {
-                if line_index == cursor.1 {
+            for (line_index, layout) in layouts.iter().enumerate() {
+                if line_index == cursor.y as usize {
                     cursor_y = y;
                 }
 
-                let clusters = &line.clusters();
-
-                for cluster in clusters {
-                    let foreground = cluster.foreground();
-                    let foreground = Color::from_rgb(foreground.0, foreground.1, foreground.2);
-
-                    text_style.set_color(foreground);
-
-                    if cluster.is_bold() {
-                        text_style.set_font_style(skia_safe::FontStyle::bold());
-                    } else {
-                        text_style.set_font_style(skia_safe::FontStyle::normal());
+                layout.paint_backgrounds(canvas, y);
+
+                // A coloured block goes under the text so it stays readable
+                if line_index == cursor.y as usize
+                    && is_cursor_visible
+                    && is_focused
+                    && cursor_style == CursorStyle::Block
+                {
+                    if let Some((r, g, b)) = cursor_color {
+                        paint.set_color(Color::from_rgb(r, g, b));
+                        canvas.draw_rect(
+                            skia_safe::Rect::from_xywh(
+                                cursor.x as f32 * cell_size.0,
+                                y,
+                                cell_size.0,
+                                cell_size.1,
+                            ),
+                            &paint,
+                        );
                     }
-
-                    paragraph_builder.push_style(&text_style);
-                    paragraph_builder.add_text(cluster.text());
-                }
-
-                let mut paragraph = paragraph_builder.build();
-                paragraph.layout(skia_safe::scalar::MAX);
-
-                let mut x = 0.;
-                for cluster in clusters {
-                    let background = cluster.background();
-                    let background = Color::from_rgb(background.0, background.1, background.2);
-                    let cluster_width = cell_size.0 * cluster.width() as f32;
-                    paint.set_color(background);
-
-                    canvas.draw_rect(
-                        skia_safe::Rect::from_xywh(x, y, cluster_width, paragraph.height()),
-                        &paint,
-                    );
-                    x += cluster_width;
                 }
 
-                paragraph.paint(canvas, (0., y));
+                layout.paint_text(canvas, y);
 
-                paragraph_builder.reset();
-
-                y += paragraph.height();
+                y += cell_size.1;
             }
 
             // draw selection
-            if let Some(selection) = &selection {
+            // The lines may have changed since, so they're checked again
+            if let (Some(selection), Some(first_line)) = (&selection, lines.first()) {
                 paint.set_color(Color::WHITE);
                 paint.set_blend_mode(skia_safe::BlendMode::Difference);
 
-                let first_line = lines.get(0).unwrap();
                 let first_line_index = first_line.index();
 
                 for rect in selection.render(first_line_index, cell_size, terminal_size) {
@@ -231,15 +458,113 @@ pub fn ContentArea(
                 }
             }
 
+            // draw the visual bell over the content
+            if bell_flash {
+                paint.set_color(Color::from_argb(40, 255, 255, 255));
+                paint.set_blend_mode(skia_safe::BlendMode::SrcOver);
+                canvas.draw_rect(
+                    skia_safe::Rect::from_xywh(
+                        0.,
+                        0.,
+                        terminal_size.0 as f32 * cell_size.0,
+                        terminal_size.1 as f32 * cell_size.1,
+                    ),
+                    &paint,
+                );
+            }
+
             // draw the cursor at the end so it sits on top everything
-            if scroll_top == 0 {
-                paint.set_color(Color::WHITE);
-                paint.set_blend_mode(skia_safe::BlendMode::Difference);
+            if is_cursor_visible {
+                let cursor_x = cursor.x as f32 * cell_size.0;
+
+                match cursor_color {
+                    Some((r, g, b)) => {
+                        paint.set_color(Color::from_rgb(r, g, b));
+                        paint.set_blend_mode(skia_safe::BlendMode::SrcOver);
+                    }
+                    None => {
+                        paint.set_color(Color::WHITE);
+                        paint.set_blend_mode(skia_safe::BlendMode::Difference);
+                    }
+                }
+
+                let cursor_rect = if !is_focused {
+                    paint.set_style(PaintStyle::Stroke);
+                    paint.set_stroke_width(1.);
+                    Some(skia_safe::Rect::from_xywh(
+                        cursor_x + 0.5,
+                        cursor_y + 0.5,
+                        cell_size.0 - 1.,
+                        cell_size.1 - 1.,
+                    ))
+                } else {
+                    match cursor_style {
+                        // Already drawn under the text
+                        CursorStyle::Block if cursor_color.is_some() => None,
+                        CursorStyle::Block => Some(skia_safe::Rect::from_xywh(
+                            cursor_x,
+                            cursor_y,
+                            cell_size.0,
+                            cell_size.1,
+                        )),
+                        CursorStyle::Bar => Some(skia_safe::Rect::from_xywh(
+                            cursor_x,
+                            cursor_y,
+                            CURSOR_BAR_WIDTH,
+                            cell_size.1,
+                        )),
+                        CursorStyle::Underline => Some(skia_safe::Rect::from_xywh(
+                            cursor_x,
+                            cursor_y + cell_size.1 - CURSOR_UNDERLINE_HEIGHT,
+                            cell_size.0,
+                            CURSOR_UNDERLINE_HEIGHT,
+                        )),
+                    }
+                };
+
+                if let Some(cursor_rect) = cursor_rect {
+                    canvas.draw_rect(cursor_rect, &paint);
+                }
+            }
+        })
+    });
+
+    let ongutterdown = {
+        let terminal = terminal.clone();
+        move |e: PointerEvent| {
+            e.stop_propagation();
+
+            let row = (e.element_coordinates.y / cell_size().1 as f64) as usize;
+            terminal.select_command_output(row);
+        }
+    };
+
+    // Exit status of the commands reported by shell integration
+    let gutter_canvas = use_canvas(move || {
+        let marks = rendered_command_marks();
+        let cell_size = cell_size();
+        Box::new(move |canvas, _, region, scale_factor| {
+            canvas.translate((region.min_x(), region.min_y()));
+            canvas.scale((scale_factor, scale_factor));
+
+            let mut paint = Paint::default();
+            paint.set_anti_alias(true);
+
+            let gutter_width = region.width() / scale_factor;
+
+            for mark in &marks {
+                let color = match mark.exit_status {
+                    Some(0) => Color::from_rgb(166, 204, 112),
+                    Some(_) => Color::from_rgb(237, 130, 116),
+                    None => Color::from_rgb(86, 91, 120),
+                };
+                paint.set_color(color);
+
                 canvas.draw_rect(
                     skia_safe::Rect::from_xywh(
-                        cursor.0 as f32 * cell_size.0,
-                        cursor_y,
-                        cell_size.0,
+                        gutter_width - GUTTER_MARK_OFFSET,
+                        mark.row as f32 * cell_size.1,
+                        GUTTER_MARK_WIDTH,
                         cell_size.1,
                     ),
                     &paint,
@@ -248,22 +573,23 @@ pub fn ContentArea(
         })
     });
 
+    if !active {
+        return None;
+    }
+
     rsx!(
         rect {
             width: "100%",
             height: "100%",
-            padding: "{padding_top} {padding_right} {padding_bottom} {padding_left}",
+            padding: "{padding.top} {padding.right} {padding.bottom} 0",
+            direction: "horizontal",
             onwheel: onwheel,
             rect {
-                width: "100%",
+                width: "{padding.left}",
                 height: "100%",
-                onpointerdown: onmousedown,
-                onpointerup: onmouseup,
-                onpointerover: onmouseover,
-                onpointerleave: onmouseleave,
-                reference: node_ref,
+                onpointerdown: ongutterdown,
                 Canvas {
-                    canvas,
+                    canvas: gutter_canvas,
                     theme: theme_with!(CanvasTheme {
                         background: "transparent".into(),
                         width: "100%".into(),
@@ -271,6 +597,30 @@ pub fn ContentArea(
                     })
                 }
             }
+            rect {
+                width: "calc(100% - {padding.left})",
+                height: "100%",
+                onpointerdown: onmousedown,
+                onpointerup: onmouseup,
+                onpointerover: onmouseover,
+                onpointerleave: onmouseleave,
+                reference: node_ref,
+                if let Some(error) = terminal_error() {
+                    label {
+                        color: "rgb(237, 130, 116)",
+                        "{error}"
+                    }
+                } else {
+                    Canvas {
+                        canvas,
+                        theme: theme_with!(CanvasTheme {
+                            background: "transparent".into(),
+                            width: "100%".into(),
+                            height: "100%".into(),
+                        })
+                    }
+                }
+            }
         }
     )
 }
@@ -1,16 +1,34 @@
-use std::sync::Arc;
-
 use freya::prelude::*;
 
-use crate::icons::TERMINAL_ICON;
-use crate::pane::Pane;
+use crate::config::TerminalConfig;
+use crate::icons::{ACTIVITY_ICON, BELL_ICON, TERMINAL_ICON};
+use crate::pane::PaneId;
+use crate::state::{AppState, PendingClose};
+use crate::utils::display_path;
 
 #[component]
 #[allow(non_snake_case)]
-pub fn Sidebar(panes: Vec<Arc<Pane>>) -> Element {
+pub fn Sidebar(
+    // App state holding the panes to list
+    mut state: Signal<AppState>,
+    // Pane being renamed, if any
+    renaming_pane: Signal<Option<PaneId>>,
+    // Title typed so far for the pane being renamed
+    rename_value: Signal<String>,
+    // Closing waiting for the user to confirm, if any
+    pending_close: Signal<Option<PendingClose>>,
+    // Terminal config, holding the launch profiles
+    config: Signal<TerminalConfig>,
+    // Width of the sidebar
+    width: f32,
+) -> Element {
+    let panes = state.read().panes();
+    let profiles = config.read().profiles.clone();
+    let active_pane_id = state.read().active_pane_id();
+
     rsx!(
         rect {
-            width: "250",
+            width: "{width}",
             height: "100%",
 
             WindowDragArea {
@@ -24,11 +42,97 @@ pub fn Sidebar(panes: Vec<Arc<Pane>>) -> Element {
                     }
 
                     for pane in panes {
-                        paragraph {
+                        rect {
+                            key: "{pane.id}",
+                            onclick: {
+                                let pane_id = pane.id;
+                                move |_| state.write().set_active_pane(pane_id)
+                            },
+
+                            paragraph {
+                                margin: "8 0",
+                                text { font_size: "12", color: "rgb(86, 91, 120, 0.6)", "{TERMINAL_ICON}" }
+                                text { "  " }
+                                if renaming_pane() == Some(pane.id) {
+                                    text { color: "rgb(165, 172, 186)", "{rename_value}▏" }
+                                } else if active_pane_id == Some(pane.id) {
+                                    text { color: "rgb(165, 172, 186)", "{pane.title()}" }
+                                } else {
+                                    text { color: "rgb(86, 91, 120)", "{pane.title()}" }
+                                }
+                                if let Some(exit_code) = pane.exit_code() {
+                                    text { font_size: "12", color: "rgb(237, 130, 116)", "  exited ({exit_code})" }
+                                }
+                                if pane.has_activity() {
+                                    text { font_size: "10", color: "rgb(166, 204, 112)", "  {ACTIVITY_ICON}" }
+                                }
+                                if pane.unread_bells() > 0 {
+                                    text { font_size: "12", color: "rgb(255, 204, 102)", "  {BELL_ICON} {pane.unread_bells()}" }
+                                }
+                            }
+
+                            if let Some(command) = pane.foreground_command() {
+                                label {
+                                    margin: "0 0 2 20",
+                                    font_size: "11",
+                                    color: "rgb(165, 172, 186, 0.8)",
+                                    max_lines: "1",
+                                    text_overflow: "ellipsis",
+                                    "{command}"
+                                }
+                            }
+
+                            if let Some(cwd) = pane.cwd() {
+                                label {
+                                    margin: "0 0 0 20",
+                                    font_size: "11",
+                                    color: "rgb(86, 91, 120, 0.8)",
+                                    max_lines: "1",
+                                    text_overflow: "ellipsis",
+                                    "{display_path(&cwd)}"
+                                }
+                            }
+                        }
+                    }
+
+                    if let Some(pending_close) = pending_close() {
+                        label {
+                            margin: "8 0",
+                            color: "rgb(255, 204, 102)",
+                            "{pending_close.prompt()}"
+                        }
+                    }
+
+                    label {
+                        margin: "24 0 0 0",
+                        "New"
+                    }
+
+                    rect {
+                        onclick: move |_| {
+                            state.write().open_pane(&config.read(), None);
+                        },
+                        label {
                             margin: "8 0",
-                            text { font_size: "12", color: "rgb(86, 91, 120, 0.6)", "{TERMINAL_ICON}" }
-                            text { "  " }
-                            text { color: "rgb(165, 172, 186)", "{pane.title()}" }
+                            color: "rgb(86, 91, 120)",
+                            "+  Terminal"
+                        }
+                    }
+
+                    for profile in profiles {
+                        rect {
+                            key: "{profile.name}",
+                            onclick: {
+                                let profile = profile.clone();
+                                move |_| {
+                                    state.write().open_pane(&config.read(), Some(&profile));
+                                }
+                            },
+                            label {
+                                margin: "8 0",
+                                color: "rgb(86, 91, 120)",
+                                "+  {profile.name}"
+                            }
                         }
                     }
                 }
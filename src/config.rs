@@ -1,6 +1,13 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+use crate::{clipboard::ImagePasteMode, fonts::BUNDLED_FONT_FAMILY, notifier::NotifierKind};
+
+#[derive(Deserialize, Clone)]
 pub struct TerminalConfig {
     // Font size of the terminal
     #[serde(default = "default_font_size")]
@@ -9,40 +16,363 @@ pub struct TerminalConfig {
     // Line height of the terminal.
     // By default it let the line height determined by the render engine.
     pub line_height: Option<f32>,
+
+    // Font families, faces and features.
+    #[serde(default)]
+    pub font: FontConfig,
+
+    // What to paste when the clipboard holds an image instead of text.
+    // Either "path", "base64" or "ignore".
+    #[serde(default)]
+    pub image_paste_mode: ImagePasteMode,
+
+    // Clipboard access for programs using OSC 52 (e.g. vim or tmux over SSH).
+    #[serde(default)]
+    pub osc52: Osc52Config,
+
+    // What to do when a program rings the bell.
+    #[serde(default)]
+    pub bell: BellConfig,
+
+    // Desktop notifications (OSC 9, OSC 777 and finished commands).
+    #[serde(default)]
+    pub notifications: NotificationConfig,
+
+    // Look of the cursor, until the program asks for another shape.
+    #[serde(default)]
+    pub cursor: CursorConfig,
+
+    // Window decorations, sidebar and padding around the terminal.
+    #[serde(default)]
+    pub window: WindowConfig,
+
+    // Ask before closing a pane, or quitting, while programs run in it.
+    // Only closing with the keyboard shortcuts asks: the window manager
+    // closing the window, e.g. from the title bar, isn't intercepted.
+    #[serde(default)]
+    pub confirm_close: ConfirmCloseConfig,
+
+    // Keep a pane open when its program exits with a non-zero code, to read
+    // its output and restart it with Enter. Off by default, shells exit with
+    // the code of the last command they ran.
+    #[serde(default)]
+    pub hold_on_failure: bool,
+
+    // Maximum number of redraws per second.
+    // Defaults to the refresh rate of the display each pane opened on, which
+    // isn't updated when the window moves to another display.
+    pub max_fps: Option<u32>,
+
+    // Load Raven's shell integration (OSC 133 prompt markers) in bash, zsh
+    // and fish.
+    #[serde(default = "default_true")]
+    pub shell_integration: bool,
+
+    // Program to run in new panes. Defaults to $SHELL, or bash.
+    pub program: Option<String>,
+
+    // Arguments passed to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    // Extra environment variables for the program
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    // Working directory of the first pane.
+    // New panes open in the directory of the active pane.
+    pub working_directory: Option<PathBuf>,
+
+    // Named programs to pick from when creating a pane,
+    // e.g. "ssh prod", "nix develop" or a python REPL.
+    #[serde(default)]
+    pub profiles: Vec<LaunchProfile>,
 }
 
-impl TerminalConfig {
-    pub fn load_from_file(&mut self) {
-        let Some(config_dir) = dirs::config_dir() else {
-            log::info!("Unable to find config dir. Not loading config...");
-            return;
+#[derive(Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct LaunchProfile {
+    // Name shown in the sidebar
+    pub name: String,
+
+    // Program to run. Defaults to the default program.
+    pub program: Option<String>,
+
+    // Arguments passed to the program
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    // Extra environment variables, on top of the default ones
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    // Working directory. Defaults to the directory of the active pane.
+    pub working_directory: Option<PathBuf>,
+
+    // Keep the pane open after the program exits
+    #[serde(default)]
+    pub hold: bool,
+}
+
+/// Program to spawn in a pane, resolved from the config.
+#[derive(Clone, PartialEq, Debug)]
+pub struct LaunchCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub cwd: Option<PathBuf>,
+    // Keep the pane open after the program exits
+    pub hold: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Osc52Config {
+    // Allow programs to set the clipboard content
+    #[serde(default = "default_true")]
+    pub allow_write: bool,
+
+    // Allow programs to read the clipboard content
+    #[serde(default)]
+    pub allow_read: bool,
+
+    // Maximum size in bytes of the content a program can set
+    #[serde(default = "default_osc52_max_size")]
+    pub max_size: usize,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct BellConfig {
+    // Briefly flash the terminal content
+    #[serde(default = "default_true")]
+    pub visual: bool,
+
+    // Show a badge next to background panes in the sidebar
+    #[serde(default = "default_true")]
+    pub badge: bool,
+
+    // Ask the window manager for attention when the window is unfocused
+    #[serde(default = "default_true")]
+    pub urgency_hint: bool,
+
+    // Command to run to play a sound, e.g. ["paplay", "/usr/share/sounds/bell.oga"].
+    // By default the bell is silent.
+    pub audible_command: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Clone, Default)]
+pub struct NotificationConfig {
+    // Where to send notifications. Either "desktop", "log" or "none".
+    #[serde(default)]
+    pub notifier: NotifierKind,
+
+    // Notify when a command running for longer than this many seconds
+    // finishes in a background pane or while the window is unfocused.
+    // Requires shell integration (OSC 133). Disabled by default.
+    pub command_finished_threshold: Option<u64>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ConfirmCloseConfig {
+    // Ask when a program other than the one the pane started runs in the
+    // foreground, e.g. vim or a build run from the shell
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    // Programs to close without asking
+    #[serde(default = "default_confirm_close_allow")]
+    pub allow: Vec<String>,
+}
+
+#[derive(Deserialize, Clone, PartialEq)]
+pub struct FontConfig {
+    // Font family of the terminal. Defaults to the bundled JetBrains Mono.
+    #[serde(default = "default_font_family")]
+    pub family: String,
+
+    // Families to look up, in order, for characters missing from the font
+    #[serde(default)]
+    pub fallback: Vec<String>,
+
+    // Families of the bold, italic and bold italic text.
+    // By default the regular family is used in a bold or italic style.
+    pub bold_family: Option<String>,
+    pub italic_family: Option<String>,
+    pub bold_italic_family: Option<String>,
+
+    // OpenType features to turn on or off, e.g. { calt = false, ss01 = true, zero = true }
+    #[serde(default)]
+    pub features: HashMap<String, bool>,
+
+    // Directory of extra font files (.ttf, .otf and .ttc) to load on top of
+    // the system fonts. Defaults to $config_dir/raven/fonts.
+    pub directory: Option<PathBuf>,
+
+    // Draw box drawing characters, block elements, braille patterns and
+    // powerline separators instead of using the font, so they line up.
+    #[serde(default = "default_true")]
+    pub builtin_glyphs: bool,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct CursorConfig {
+    // Either "block", "bar" or "underline"
+    #[serde(default)]
+    pub style: CursorStyle,
+
+    // Blink the cursor
+    #[serde(default)]
+    pub blinking: bool,
+
+    // Milliseconds the cursor stays shown, then hidden, when blinking.
+    // 0 disables blinking, even for programs asking for it.
+    #[serde(default = "default_cursor_blink_interval")]
+    pub blink_interval: u64,
+
+    // Colour of the cursor, e.g. "#ffcc66" or "orange".
+    // By default the cursor inverts the content under it.
+    pub color: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WindowConfig {
+    // Use the title bar and borders of the system.
+    // By default the window is moved by dragging the sidebar.
+    #[serde(default)]
+    pub decorations: bool,
+
+    // Show the sidebar when starting. Toggled with Ctrl+Shift+B.
+    #[serde(default = "default_true")]
+    pub sidebar: bool,
+
+    // Width of the sidebar when starting. Resized by dragging its edge.
+    #[serde(default = "default_sidebar_width")]
+    pub sidebar_width: f32,
+
+    // Space around the terminal content, e.g. { top = 10, left = 40 }.
+    // The command marks of shell integration are drawn in the left padding.
+    #[serde(default)]
+    pub padding: Padding,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct Padding {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum CursorStyle {
+    #[default]
+    Block,
+    Bar,
+    Underline,
+}
+
+impl ConfirmCloseConfig {
+    /// Whether the program can be closed without asking.
+    pub fn can_close(&self, program: &str) -> bool {
+        !self.enabled || self.allow.iter().any(|allowed| allowed == program)
+    }
+}
+
+impl FontConfig {
+    /// Families to render text with, by order of preference.
+    pub fn families(&self, bold: bool, italic: bool) -> Vec<String> {
+        let face_family = match (bold, italic) {
+            (true, true) => self.bold_italic_family.as_ref(),
+            (true, false) => self.bold_family.as_ref(),
+            (false, true) => self.italic_family.as_ref(),
+            (false, false) => None,
         };
 
-        let config_file = config_dir.join("raven").join("config.toml");
+        let mut families = Vec::new();
+        families.extend(face_family.cloned());
+        families.push(self.family.clone());
+        families.extend(self.fallback.iter().cloned());
+        // Keep the bundled font last so Nerd Font icons always render
+        families.push(String::from(BUNDLED_FONT_FAMILY));
+        families
+    }
+}
+
+impl TerminalConfig {
+    /// Loads the config from `path`, or from the config dir when not given.
+    /// Loads the given config file, or the default one. Only failing to load
+    /// a file given explicitly is an error, the default one is optional.
+    pub fn load_from_file(&mut self, path: Option<&Path>) -> Result<(), String> {
+        let config_file = match path {
+            Some(path) => path.to_path_buf(),
+            None => {
+                let Some(config_dir) = dirs::config_dir() else {
+                    log::info!("Unable to find config dir. Not loading config...");
+                    return Ok(());
+                };
+                config_dir.join("raven").join("config.toml")
+            }
+        };
 
-        let content = match std::fs::read_to_string(config_file) {
+        let content = match std::fs::read_to_string(&config_file) {
             Ok(content) => content,
+            Err(e) if path.is_some() => {
+                return Err(format!("unable to read {}: {}", config_file.display(), e));
+            }
             Err(e) => {
                 log::info!(
                     "Unable to read config file content. Not loading config...\n{}",
                     e.to_string()
                 );
-                return;
+                return Ok(());
             }
         };
 
         let config = match toml::from_str(&content) {
             Ok(config) => config,
+            Err(e) if path.is_some() => {
+                return Err(format!("unable to parse {}: {}", config_file.display(), e));
+            }
             Err(e) => {
                 log::info!(
                     "Unable to parse config file. Not loading config...\n{}",
                     e.to_string()
                 );
-                return;
+                return Ok(());
             }
         };
 
         *self = config;
+        Ok(())
+    }
+
+    /// Command to run in a new pane, using the given profile if any.
+    pub fn launch_command(&self, profile: Option<&LaunchProfile>) -> LaunchCommand {
+        let program = profile
+            .and_then(|profile| profile.program.clone())
+            .or_else(|| self.program.clone())
+            .or_else(|| std::env::var("SHELL").ok())
+            .unwrap_or(String::from("bash"));
+
+        let args = match profile {
+            Some(profile) if profile.program.is_some() || !profile.args.is_empty() => {
+                profile.args.clone()
+            }
+            _ => self.args.clone(),
+        };
+
+        let mut env = self.env.clone();
+        if let Some(profile) = profile {
+            env.extend(profile.env.clone());
+        }
+
+        LaunchCommand {
+            program,
+            args,
+            env,
+            cwd: profile.and_then(|profile| profile.working_directory.clone()),
+            hold: profile.is_some_and(|profile| profile.hold),
+        }
     }
 
     pub fn set_font_size(&mut self, font_size: f32) {
@@ -55,6 +385,32 @@ impl Default for TerminalConfig {
         Self {
             font_size: default_font_size(),
             line_height: None,
+            font: FontConfig::default(),
+            image_paste_mode: ImagePasteMode::default(),
+            osc52: Osc52Config::default(),
+            bell: BellConfig::default(),
+            notifications: NotificationConfig::default(),
+            cursor: CursorConfig::default(),
+            window: WindowConfig::default(),
+            confirm_close: ConfirmCloseConfig::default(),
+            hold_on_failure: false,
+            max_fps: None,
+            shell_integration: default_true(),
+            program: None,
+            args: Vec::new(),
+            env: HashMap::new(),
+            working_directory: None,
+            profiles: Vec::new(),
+        }
+    }
+}
+
+impl Default for Osc52Config {
+    fn default() -> Self {
+        Self {
+            allow_write: default_true(),
+            allow_read: false,
+            max_size: default_osc52_max_size(),
         }
     }
 }
@@ -62,3 +418,100 @@ impl Default for TerminalConfig {
 fn default_font_size() -> f32 {
     14.
 }
+
+impl Default for BellConfig {
+    fn default() -> Self {
+        Self {
+            visual: default_true(),
+            badge: default_true(),
+            urgency_hint: default_true(),
+            audible_command: None,
+        }
+    }
+}
+
+impl Default for ConfirmCloseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(),
+            allow: default_confirm_close_allow(),
+        }
+    }
+}
+
+fn default_confirm_close_allow() -> Vec<String> {
+    // Nested shells
+    ["bash", "zsh", "fish", "sh", "dash"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            family: default_font_family(),
+            fallback: Vec::new(),
+            bold_family: None,
+            italic_family: None,
+            bold_italic_family: None,
+            features: HashMap::new(),
+            directory: None,
+            builtin_glyphs: default_true(),
+        }
+    }
+}
+
+fn default_font_family() -> String {
+    String::from(BUNDLED_FONT_FAMILY)
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            decorations: false,
+            sidebar: default_true(),
+            sidebar_width: default_sidebar_width(),
+            padding: Padding::default(),
+        }
+    }
+}
+
+fn default_sidebar_width() -> f32 {
+    250.
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Self {
+            top: 50.,
+            right: 50.,
+            bottom: 40.,
+            left: 100.,
+        }
+    }
+}
+
+impl Default for CursorConfig {
+    fn default() -> Self {
+        Self {
+            style: CursorStyle::default(),
+            blinking: false,
+            blink_interval: default_cursor_blink_interval(),
+            color: None,
+        }
+    }
+}
+
+fn default_cursor_blink_interval() -> u64 {
+    500
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_osc52_max_size() -> usize {
+    // 1 MiB
+    1024 * 1024
+}
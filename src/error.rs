@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// Failure of a terminal, from starting its program to running it.
+#[derive(Clone, Debug)]
+pub enum TerminalError {
+    // No PTY could be opened, e.g. all of them are in use
+    OpenPty(String),
+    // The program couldn't be started, e.g. it doesn't exist
+    Spawn { program: String, reason: String },
+    // Reading, writing or resizing the PTY failed
+    Pty(String),
+    // The thread running the terminal couldn't be started
+    Thread(String),
+    // The terminal stopped on an unexpected error
+    Stopped(String),
+}
+
+impl TerminalError {
+    pub fn open_pty(error: anyhow::Error) -> Self {
+        Self::OpenPty(format!("{:#}", error))
+    }
+
+    pub fn spawn(program: &str, error: anyhow::Error) -> Self {
+        Self::Spawn {
+            program: program.to_string(),
+            reason: format!("{:#}", error),
+        }
+    }
+
+    pub fn pty(error: anyhow::Error) -> Self {
+        Self::Pty(format!("{:#}", error))
+    }
+
+    /// Error the terminal loop stopped on.
+    pub fn stopped(error: anyhow::Error) -> Self {
+        match error.downcast::<TerminalError>() {
+            Ok(error) => error,
+            Err(error) => Self::Stopped(format!("{:#}", error)),
+        }
+    }
+}
+
+impl fmt::Display for TerminalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OpenPty(reason) => write!(f, "failed to open a PTY: {}", reason),
+            Self::Spawn { program, reason } => write!(f, "failed to spawn {}: {}", program, reason),
+            Self::Pty(reason) => write!(f, "PTY failed: {}", reason),
+            Self::Thread(reason) => write!(f, "failed to start the terminal: {}", reason),
+            Self::Stopped(reason) => write!(f, "terminal stopped: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for TerminalError {}
@@ -0,0 +1,106 @@
+use std::{path::Path, sync::OnceLock};
+
+use skia_safe::{
+    textlayout::{FontCollection, TypefaceFontProvider},
+    FontMgr,
+};
+
+use crate::config::FontConfig;
+
+const JETBRAINS_MONO: &[u8] = include_bytes!("../assets/JetBrainsMonoNerdFont-Regular.ttf");
+
+/// Family the bundled font is registered as.
+pub const BUNDLED_FONT_FAMILY: &str = "jetbrains mono";
+
+// Fonts registered on top of the system ones, as (family, data)
+static FONTS: OnceLock<Vec<(&'static str, &'static [u8])>> = OnceLock::new();
+
+/// Loads the bundled font and the fonts of the user font directory. Has to
+/// be called once, before any font is used.
+pub fn load_fonts(config: &FontConfig) -> &'static [(&'static str, &'static [u8])] {
+    FONTS.get_or_init(|| {
+        let mut fonts = vec![(BUNDLED_FONT_FAMILY, JETBRAINS_MONO)];
+
+        let font_dir = config.directory.clone().or_else(|| {
+            dirs::config_dir().map(|config_dir| config_dir.join("raven").join("fonts"))
+        });
+
+        if let Some(font_dir) = font_dir {
+            fonts.extend(load_font_dir(&font_dir));
+        }
+
+        fonts
+    })
+}
+
+thread_local! {
+    static FONT_COLLECTION: FontCollection = create_font_collection();
+}
+
+/// Fonts the terminal can use: system fonts (fontconfig on Linux) plus the
+/// loaded ones.
+pub fn font_collection() -> FontCollection {
+    FONT_COLLECTION.with(FontCollection::clone)
+}
+
+fn create_font_collection() -> FontCollection {
+    let font_mgr = FontMgr::default();
+
+    let mut provider = TypefaceFontProvider::new();
+    for (family, data) in FONTS.get().map(Vec::as_slice).unwrap_or_default() {
+        if let Some(typeface) = font_mgr.new_from_data(data, None) {
+            provider.register_typeface(typeface, Some(*family));
+        }
+    }
+
+    let mut font_collection = FontCollection::new();
+    font_collection.set_default_font_manager(font_mgr, None);
+    font_collection.set_dynamic_font_manager(FontMgr::from(provider));
+    font_collection
+}
+
+fn load_font_dir(font_dir: &Path) -> Vec<(&'static str, &'static [u8])> {
+    let entries = match std::fs::read_dir(font_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::info!(
+                "Unable to read font dir {}. Not loading fonts...\n{}",
+                font_dir.display(),
+                e
+            );
+            return Vec::new();
+        }
+    };
+
+    let font_mgr = FontMgr::default();
+
+    entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    ["ttf", "otf", "ttc"].contains(&extension.to_lowercase().as_str())
+                })
+        })
+        .filter_map(|path| {
+            let data = match std::fs::read(&path) {
+                Ok(data) => data,
+                Err(e) => {
+                    log::warn!("Unable to read font {}.\n{}", path.display(), e);
+                    return None;
+                }
+            };
+
+            let Some(typeface) = font_mgr.new_from_data(&data, None) else {
+                log::warn!("Unable to load font {}.", path.display());
+                return None;
+            };
+
+            // Fonts are loaded once for the whole lifetime of the app
+            let family: &'static str = Box::leak(typeface.family_name().into_boxed_str());
+            let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+            Some((family, data))
+        })
+        .collect()
+}
@@ -0,0 +1,389 @@
+use skia_safe::{Canvas, Color, Paint, PaintStyle, Path, Rect};
+
+// Weight of each arm of a box drawing character, 1 being a light line
+const NONE: u8 = 0;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+// Arms (up, right, down, left) of the box drawing characters U+2500 to U+254F.
+// Dashed lines (U+2504 to U+250B and U+254C to U+254F) are listed as solid
+// lines and split into dashes when drawn.
+#[rustfmt::skip]
+const LINE_ARMS: [[u8; 4]; 80] = [
+    // ─ ━ │ ┃ ┄ ┅ ┆ ┇
+    [0, 1, 0, 1], [0, 2, 0, 2], [1, 0, 1, 0], [2, 0, 2, 0],
+    [0, 1, 0, 1], [0, 2, 0, 2], [1, 0, 1, 0], [2, 0, 2, 0],
+    // ┈ ┉ ┊ ┋ ┌ ┍ ┎ ┏
+    [0, 1, 0, 1], [0, 2, 0, 2], [1, 0, 1, 0], [2, 0, 2, 0],
+    [0, 1, 1, 0], [0, 2, 1, 0], [0, 1, 2, 0], [0, 2, 2, 0],
+    // ┐ ┑ ┒ ┓ └ ┕ ┖ ┗
+    [0, 0, 1, 1], [0, 0, 1, 2], [0, 0, 2, 1], [0, 0, 2, 2],
+    [1, 1, 0, 0], [1, 2, 0, 0], [2, 1, 0, 0], [2, 2, 0, 0],
+    // ┘ ┙ ┚ ┛ ├ ┝ ┞ ┟
+    [1, 0, 0, 1], [1, 0, 0, 2], [2, 0, 0, 1], [2, 0, 0, 2],
+    [1, 1, 1, 0], [1, 2, 1, 0], [2, 1, 1, 0], [1, 1, 2, 0],
+    // ┠ ┡ ┢ ┣ ┤ ┥ ┦ ┧
+    [2, 1, 2, 0], [2, 2, 1, 0], [1, 2, 2, 0], [2, 2, 2, 0],
+    [1, 0, 1, 1], [1, 0, 1, 2], [2, 0, 1, 1], [1, 0, 2, 1],
+    // ┨ ┩ ┪ ┫ ┬ ┭ ┮ ┯
+    [2, 0, 2, 1], [2, 0, 1, 2], [1, 0, 2, 2], [2, 0, 2, 2],
+    [0, 1, 1, 1], [0, 1, 1, 2], [0, 2, 1, 1], [0, 2, 1, 2],
+    // ┰ ┱ ┲ ┳ ┴ ┵ ┶ ┷
+    [0, 1, 2, 1], [0, 1, 2, 2], [0, 2, 2, 1], [0, 2, 2, 2],
+    [1, 1, 0, 1], [1, 1, 0, 2], [1, 2, 0, 1], [1, 2, 0, 2],
+    // ┸ ┹ ┺ ┻ ┼ ┽ ┾ ┿
+    [2, 1, 0, 1], [2, 1, 0, 2], [2, 2, 0, 1], [2, 2, 0, 2],
+    [1, 1, 1, 1], [1, 1, 1, 2], [1, 2, 1, 1], [1, 2, 1, 2],
+    // ╀ ╁ ╂ ╃ ╄ ╅ ╆ ╇
+    [2, 1, 1, 1], [1, 1, 2, 1], [2, 1, 2, 1], [2, 1, 1, 2],
+    [2, 2, 1, 1], [1, 1, 2, 2], [1, 2, 2, 1], [2, 2, 1, 2],
+    // ╈ ╉ ╊ ╋ ╌ ╍ ╎ ╏
+    [1, 2, 2, 2], [2, 1, 2, 2], [2, 2, 2, 1], [2, 2, 2, 2],
+    [0, 1, 0, 1], [0, 2, 0, 2], [1, 0, 1, 0], [2, 0, 2, 0],
+];
+
+// Arms of the double line characters U+2550 to U+256C
+#[rustfmt::skip]
+const DOUBLE_ARMS: [[u8; 4]; 29] = [
+    // ═ ║ ╒ ╓ ╔ ╕ ╖ ╗
+    [0, 3, 0, 3], [3, 0, 3, 0], [0, 3, 1, 0], [0, 1, 3, 0],
+    [0, 3, 3, 0], [0, 0, 1, 3], [0, 0, 3, 1], [0, 0, 3, 3],
+    // ╘ ╙ ╚ ╛ ╜ ╝ ╞ ╟
+    [1, 3, 0, 0], [3, 1, 0, 0], [3, 3, 0, 0], [1, 0, 0, 3],
+    [3, 0, 0, 1], [3, 0, 0, 3], [1, 3, 1, 0], [3, 1, 3, 0],
+    // ╠ ╡ ╢ ╣ ╤ ╥ ╦ ╧
+    [3, 3, 3, 0], [1, 0, 1, 3], [3, 0, 3, 1], [3, 0, 3, 3],
+    [0, 3, 1, 3], [0, 1, 3, 1], [0, 3, 3, 3], [1, 3, 0, 3],
+    // ╨ ╩ ╪ ╫ ╬
+    [3, 1, 0, 1], [3, 3, 0, 3], [1, 3, 1, 3], [3, 1, 3, 1],
+    [3, 3, 3, 3],
+];
+
+// Arms of the half lines U+2574 to U+257F
+#[rustfmt::skip]
+const HALF_ARMS: [[u8; 4]; 12] = [
+    // ╴ ╵ ╶ ╷ ╸ ╹ ╺ ╻ ╼ ╽ ╾ ╿
+    [0, 0, 0, 1], [1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0],
+    [0, 0, 0, 2], [2, 0, 0, 0], [0, 2, 0, 0], [0, 0, 2, 0],
+    [0, 2, 0, 1], [1, 0, 2, 0], [0, 1, 0, 2], [2, 0, 1, 0],
+];
+
+/// Whether the character is drawn by [`draw_builtin_glyph`] instead of
+/// using the font.
+pub fn is_builtin_glyph(ch: char) -> bool {
+    matches!(ch, '\u{2500}'..='\u{259F}' | '\u{2800}'..='\u{28FF}' | '\u{E0B0}'..='\u{E0B7}')
+}
+
+/// Draws box drawing characters, block elements, braille patterns and
+/// powerline separators so they fill the cell and connect with their
+/// neighbours, whatever the font. Returns false for other characters.
+pub fn draw_builtin_glyph(canvas: &Canvas, ch: char, cell: Rect, color: Color) -> bool {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.set_anti_alias(true);
+
+    let code = ch as u32;
+    match ch {
+        '\u{2500}'..='\u{254F}' => {
+            let dashes = match code {
+                0x2504..=0x2507 => 3,
+                0x2508..=0x250B => 4,
+                0x254C..=0x254F => 2,
+                _ => 0,
+            };
+            draw_lines(
+                canvas,
+                &paint,
+                cell,
+                LINE_ARMS[(code - 0x2500) as usize],
+                dashes,
+            );
+        }
+        '\u{2550}'..='\u{256C}' => {
+            draw_lines(
+                canvas,
+                &paint,
+                cell,
+                DOUBLE_ARMS[(code - 0x2550) as usize],
+                0,
+            );
+        }
+        '\u{256D}'..='\u{2570}' => draw_arc(canvas, &mut paint, cell, ch),
+        '\u{2571}'..='\u{2573}' => draw_diagonals(canvas, &mut paint, cell, ch),
+        '\u{2574}'..='\u{257F}' => {
+            draw_lines(canvas, &paint, cell, HALF_ARMS[(code - 0x2574) as usize], 0);
+        }
+        '\u{2580}'..='\u{259F}' => draw_block(canvas, &mut paint, cell, ch),
+        '\u{2800}'..='\u{28FF}' => draw_braille(canvas, &paint, cell, (code - 0x2800) as u8),
+        '\u{E0B0}'..='\u{E0B7}' => draw_powerline(canvas, &mut paint, cell, ch),
+        _ => return false,
+    }
+
+    true
+}
+
+fn line_thickness(cell: Rect) -> f32 {
+    (cell.width() / 8.).round().max(1.)
+}
+
+fn draw_lines(canvas: &Canvas, paint: &Paint, cell: Rect, arms: [u8; 4], dashes: usize) {
+    let light = line_thickness(cell);
+    let (center_x, center_y) = (cell.center_x(), cell.center_y());
+    // Gap between the two lines of a double line
+    let gap = light;
+
+    let [up, right, down, left] = arms;
+
+    let draw_line = |weight: u8, vertical: bool, from: f32, to: f32, offset: f32| {
+        let half_thickness = if weight == HEAVY { light } else { light / 2. };
+
+        let (start, end) = (from.min(to), from.max(to));
+        for (start, end) in split_dashes(start, end, dashes) {
+            let rect = if vertical {
+                Rect::from_ltrb(
+                    center_x + offset - half_thickness,
+                    start,
+                    center_x + offset + half_thickness,
+                    end,
+                )
+            } else {
+                Rect::from_ltrb(
+                    start,
+                    center_y + offset - half_thickness,
+                    end,
+                    center_y + offset + half_thickness,
+                )
+            };
+            canvas.draw_rect(rect, paint);
+        }
+    };
+
+    if dashes > 0 {
+        // Dashed lines cross the whole cell
+        if up.max(down) != NONE {
+            draw_line(up.max(down), true, cell.top, cell.bottom, 0.);
+        }
+        if left.max(right) != NONE {
+            draw_line(left.max(right), false, cell.left, cell.right, 0.);
+        }
+        return;
+    }
+
+    // How far past the center a light or heavy line goes to cover the
+    // perpendicular lines it joins
+    let single_reach = |perpendicular: u8| match perpendicular {
+        DOUBLE => gap + light / 2.,
+        HEAVY => light,
+        _ => light / 2.,
+    };
+
+    // Each line of a double arm stops at the perpendicular line on its side.
+    // Without one, it runs on into the opposite arm, or up to the
+    // perpendicular line on the other side, so corners and tees are closed
+    // without crossing.
+    let double_reach = |same_side: u8, other_side: u8, opposite: u8| match (same_side, other_side) {
+        (DOUBLE, _) => light / 2. - gap,
+        _ if opposite == DOUBLE => light / 2.,
+        (_, DOUBLE) => gap + light / 2.,
+        _ => single_reach(same_side.max(other_side)),
+    };
+
+    // Arms with the edge they start from, the direction of that edge, the
+    // perpendicular arms before and after them and the opposite arm
+    for (weight, vertical, edge, direction, [before, after], opposite) in [
+        (up, true, cell.top, -1., [left, right], down),
+        (down, true, cell.bottom, 1., [left, right], up),
+        (left, false, cell.left, -1., [up, down], right),
+        (right, false, cell.right, 1., [up, down], left),
+    ] {
+        let center = if vertical { center_y } else { center_x };
+        let lines = match weight {
+            NONE => continue,
+            DOUBLE => vec![
+                (-gap, double_reach(before, after, opposite)),
+                (gap, double_reach(after, before, opposite)),
+            ],
+            _ => vec![(0., single_reach(before.max(after)))],
+        };
+
+        for (offset, reach) in lines {
+            draw_line(weight, vertical, edge, center - direction * reach, offset);
+        }
+    }
+}
+
+/// Splits a line into `dashes` evenly spaced dashes, or keeps it whole.
+fn split_dashes(start: f32, end: f32, dashes: usize) -> Vec<(f32, f32)> {
+    if dashes == 0 {
+        return vec![(start, end)];
+    }
+
+    let step = (end - start) / dashes as f32;
+    (0..dashes)
+        .map(|i| {
+            let dash_start = start + step * i as f32;
+            (dash_start, dash_start + step * 0.6)
+        })
+        .collect()
+}
+
+fn draw_arc(canvas: &Canvas, paint: &mut Paint, cell: Rect, ch: char) {
+    paint.set_style(PaintStyle::Stroke);
+    paint.set_stroke_width(line_thickness(cell));
+
+    let (center_x, center_y) = (cell.center_x(), cell.center_y());
+    let radius = cell.width().min(cell.height()) / 2.;
+
+    // Vertical and horizontal edges the arc connects
+    let (edge_y, edge_x, direction_y, direction_x) = match ch {
+        '╭' => (cell.bottom, cell.right, 1., 1.),
+        '╮' => (cell.bottom, cell.left, 1., -1.),
+        '╯' => (cell.top, cell.left, -1., -1.),
+        _ => (cell.top, cell.right, -1., 1.),
+    };
+
+    let mut path = Path::new();
+    path.move_to((center_x, edge_y));
+    path.line_to((center_x, center_y + radius * direction_y));
+    path.quad_to(
+        (center_x, center_y),
+        (center_x + radius * direction_x, center_y),
+    );
+    path.line_to((edge_x, center_y));
+    canvas.draw_path(&path, paint);
+}
+
+fn draw_diagonals(canvas: &Canvas, paint: &mut Paint, cell: Rect, ch: char) {
+    paint.set_style(PaintStyle::Stroke);
+    paint.set_stroke_width(line_thickness(cell));
+
+    if ch != '╲' {
+        canvas.draw_line((cell.right, cell.top), (cell.left, cell.bottom), paint);
+    }
+    if ch != '╱' {
+        canvas.draw_line((cell.left, cell.top), (cell.right, cell.bottom), paint);
+    }
+}
+
+fn draw_block(canvas: &Canvas, paint: &mut Paint, cell: Rect, ch: char) {
+    // Blocks are drawn edge to edge, anti-aliasing would leave seams
+    paint.set_anti_alias(false);
+
+    let (width, height) = (cell.width(), cell.height());
+    let eighths = |count: u32| count as f32 / 8.;
+
+    let rect = match ch {
+        '▀' => Rect::from_xywh(cell.left, cell.top, width, height / 2.),
+        // Lower blocks, from one eighth to full
+        '\u{2581}'..='\u{2588}' => {
+            let block_height = height * eighths(ch as u32 - 0x2580);
+            Rect::from_xywh(cell.left, cell.bottom - block_height, width, block_height)
+        }
+        // Left blocks, from seven eighths to one eighth
+        '\u{2589}'..='\u{258F}' => Rect::from_xywh(
+            cell.left,
+            cell.top,
+            width * eighths(0x2590 - ch as u32),
+            height,
+        ),
+        '▐' => Rect::from_xywh(cell.center_x(), cell.top, width / 2., height),
+        '░' | '▒' | '▓' => {
+            let alpha = (ch as u32 - 0x2590) as f32 / 4.;
+            paint.set_alpha_f(paint.alpha_f() * alpha);
+            cell
+        }
+        '▔' => Rect::from_xywh(cell.left, cell.top, width, height / 8.),
+        '▕' => Rect::from_xywh(cell.right - width / 8., cell.top, width / 8., height),
+        _ => {
+            draw_quadrants(canvas, paint, cell, ch);
+            return;
+        }
+    };
+
+    canvas.draw_rect(rect, paint);
+}
+
+fn draw_quadrants(canvas: &Canvas, paint: &Paint, cell: Rect, ch: char) {
+    const UPPER_LEFT: u8 = 1;
+    const UPPER_RIGHT: u8 = 2;
+    const LOWER_LEFT: u8 = 4;
+    const LOWER_RIGHT: u8 = 8;
+
+    let quadrants = match ch {
+        '▖' => LOWER_LEFT,
+        '▗' => LOWER_RIGHT,
+        '▘' => UPPER_LEFT,
+        '▙' => UPPER_LEFT | LOWER_LEFT | LOWER_RIGHT,
+        '▚' => UPPER_LEFT | LOWER_RIGHT,
+        '▛' => UPPER_LEFT | UPPER_RIGHT | LOWER_LEFT,
+        '▜' => UPPER_LEFT | UPPER_RIGHT | LOWER_RIGHT,
+        '▝' => UPPER_RIGHT,
+        '▞' => UPPER_RIGHT | LOWER_LEFT,
+        '▟' => UPPER_RIGHT | LOWER_LEFT | LOWER_RIGHT,
+        _ => return,
+    };
+
+    let (half_width, half_height) = (cell.width() / 2., cell.height() / 2.);
+    for (quadrant, x, y) in [
+        (UPPER_LEFT, cell.left, cell.top),
+        (UPPER_RIGHT, cell.center_x(), cell.top),
+        (LOWER_LEFT, cell.left, cell.center_y()),
+        (LOWER_RIGHT, cell.center_x(), cell.center_y()),
+    ] {
+        if quadrants & quadrant != 0 {
+            canvas.draw_rect(Rect::from_xywh(x, y, half_width, half_height), paint);
+        }
+    }
+}
+
+fn draw_braille(canvas: &Canvas, paint: &Paint, cell: Rect, dots: u8) {
+    // Bit of each dot, by row then column
+    const DOT_BITS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    let radius = (cell.width() / 4.).min(cell.height() / 8.) * 0.6;
+
+    for (row, bits) in DOT_BITS.iter().enumerate() {
+        for (column, bit) in bits.iter().enumerate() {
+            if dots & bit == 0 {
+                continue;
+            }
+            let x = cell.left + cell.width() * (column * 2 + 1) as f32 / 4.;
+            let y = cell.top + cell.height() * (row * 2 + 1) as f32 / 8.;
+            canvas.draw_circle((x, y), radius, paint);
+        }
+    }
+}
+
+fn draw_powerline(canvas: &Canvas, paint: &mut Paint, cell: Rect, ch: char) {
+    let points_right = matches!(ch, '\u{E0B0}' | '\u{E0B1}' | '\u{E0B4}' | '\u{E0B5}');
+    let is_outline = matches!(ch, '\u{E0B1}' | '\u{E0B3}' | '\u{E0B5}' | '\u{E0B7}');
+    let is_rounded = ch >= '\u{E0B4}';
+
+    let (base_x, tip_x) = if points_right {
+        (cell.left, cell.right)
+    } else {
+        (cell.right, cell.left)
+    };
+
+    let mut path = Path::new();
+    path.move_to((base_x, cell.top));
+    if is_rounded {
+        path.cubic_to(
+            (tip_x, cell.top),
+            (tip_x, cell.bottom),
+            (base_x, cell.bottom),
+        );
+    } else {
+        path.line_to((tip_x, cell.center_y()));
+        path.line_to((base_x, cell.bottom));
+    }
+
+    if is_outline {
+        paint.set_style(PaintStyle::Stroke);
+        paint.set_stroke_width(line_thickness(cell));
+    } else {
+        path.close();
+    }
+
+    canvas.draw_path(&path, paint);
+}
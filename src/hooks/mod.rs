@@ -1,5 +1,7 @@
 mod use_debounce;
 mod use_terminal;
+mod use_window_focus;
 
 pub use use_debounce::*;
 pub use use_terminal::*;
+pub use use_window_focus::*;
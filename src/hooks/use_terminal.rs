@@ -11,6 +11,10 @@ pub struct UseTerminal {
 }
 
 impl UseTerminal {
+    pub fn new(pane: Arc<Pane>) -> Self {
+        Self { pane }
+    }
+
     pub fn paste(&self, content: String) {
         self.send_event(UserEvent::Paste(content));
     }
@@ -43,6 +47,14 @@ impl UseTerminal {
         self.send_event(UserEvent::Scroll(delta_y));
     }
 
+    pub fn scroll_to_prompt(&self, direction: isize) {
+        self.send_event(UserEvent::ScrollToPrompt(direction));
+    }
+
+    pub fn select_command_output(&self, row: usize) {
+        self.send_event(UserEvent::SelectCommandOutput(row));
+    }
+
     pub fn mouse_down(&self, event: PointerEvent, cell_size: (f32, f32)) {
         self.send_mouse_event(event, wezterm_term::MouseEventKind::Press, cell_size);
     }
@@ -100,5 +112,5 @@ impl UseTerminal {
 }
 
 pub fn use_terminal(pane: Arc<Pane>) -> UseTerminal {
-    use_hook(|| UseTerminal { pane })
+    use_hook(|| UseTerminal::new(pane))
 }
@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use freya::prelude::*;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Whether the window currently has the focus.
+/// The focus is polled from the window as it isn't exposed as an event.
+pub fn use_window_focus() -> Signal<bool, SyncStorage> {
+    let platform = use_platform();
+    let is_focused = use_signal_sync(|| true);
+
+    use_hook(move || {
+        spawn(async move {
+            loop {
+                platform.with_window(move |window| {
+                    let has_focus = window.has_focus();
+                    if *is_focused.peek() != has_focus {
+                        let mut is_focused = is_focused;
+                        is_focused.set(has_focus);
+                    }
+                });
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        });
+    });
+
+    is_focused
+}
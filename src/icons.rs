@@ -1 +1,3 @@
 pub const TERMINAL_ICON: char = '';
+pub const BELL_ICON: char = '';
+pub const ACTIVITY_ICON: char = '●';
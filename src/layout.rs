@@ -0,0 +1,348 @@
+use std::{collections::HashMap, rc::Rc};
+
+use skia_safe::{
+    scalar,
+    textlayout::{
+        FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextDecoration,
+        TextDecorationStyle, TextStyle,
+    },
+    Canvas, Color, FontStyle, Paint, Rect,
+};
+use termwiz::surface::SequenceNo;
+use wezterm_term::{Blink, StableRowIndex, Underline};
+
+use crate::{
+    config::FontConfig,
+    glyphs::{draw_builtin_glyph, is_builtin_glyph},
+    rendering::{LineElement, LineSegment},
+};
+
+/// Settings the lines are laid out with. Changing any of them invalidates
+/// every cached layout.
+#[derive(Clone, PartialEq)]
+pub struct LayoutSettings {
+    pub font: FontConfig,
+    pub font_size: f32,
+    pub line_height: Option<f32>,
+    pub cell_size: (f32, f32),
+}
+
+enum Glyphs {
+    Text {
+        paragraph: Paragraph,
+        x: f32,
+        y: f32,
+        scale: f32,
+    },
+    Builtin {
+        ch: char,
+        cell: Rect,
+        color: Color,
+    },
+}
+
+/// Line shaped and positioned on the cell grid, ready to be painted.
+pub struct LineLayout {
+    backgrounds: Vec<(Rect, Color)>,
+    glyphs: Vec<Glyphs>,
+}
+
+impl LineLayout {
+    pub fn paint_backgrounds(&self, canvas: &Canvas, y: f32) {
+        let mut paint = Paint::default();
+        for (rect, color) in &self.backgrounds {
+            paint.set_color(*color);
+            canvas.draw_rect(rect.with_offset((0., y)), &paint);
+        }
+    }
+
+    pub fn paint_text(&self, canvas: &Canvas, y: f32) {
+        for glyphs in &self.glyphs {
+            match glyphs {
+                Glyphs::Text {
+                    paragraph,
+                    x,
+                    y: glyphs_y,
+                    scale,
+                } => {
+                    if *scale == 1. {
+                        paragraph.paint(canvas, (*x, y + glyphs_y));
+                        continue;
+                    }
+                    canvas.save();
+                    canvas.translate((*x, y + glyphs_y));
+                    canvas.scale((*scale, *scale));
+                    paragraph.paint(canvas, (0., 0.));
+                    canvas.restore();
+                }
+                Glyphs::Builtin { ch, cell, color } => {
+                    draw_builtin_glyph(canvas, *ch, cell.with_offset((0., y)), *color);
+                }
+            }
+        }
+    }
+}
+
+struct CachedLine {
+    line: LineElement,
+    blink_phase: usize,
+    layout: Rc<LineLayout>,
+}
+
+/// Layouts of the lines painted in the previous frame, so only the lines
+/// that changed get shaped again.
+#[derive(Default)]
+pub struct LineLayoutCache {
+    settings: Option<LayoutSettings>,
+    // By stable row and seqno of the line
+    lines: HashMap<(StableRowIndex, SequenceNo), CachedLine>,
+}
+
+impl LineLayoutCache {
+    /// Layouts of the given lines, reusing the cached ones of the lines that
+    /// didn't change.
+    pub fn layout_lines(
+        &mut self,
+        lines: &[LineElement],
+        settings: &LayoutSettings,
+        font_collection: &FontCollection,
+        text_blink_tick: usize,
+    ) -> Vec<Rc<LineLayout>> {
+        if self.settings.as_ref() != Some(settings) {
+            self.settings = Some(settings.clone());
+            self.lines.clear();
+        }
+
+        let mut shaper = None;
+        let mut layouts = Vec::with_capacity(lines.len());
+        let mut cached_lines = HashMap::with_capacity(lines.len());
+
+        for line in lines {
+            // Lines with blinking text look different in each blink phase
+            let blink_phase = if line.has_blinking_text() {
+                text_blink_tick % 4
+            } else {
+                0
+            };
+
+            // Moved rather than cloned, most lines are reused on each frame
+            let cached = self
+                .lines
+                .remove(&line.key())
+                .filter(|cached| cached.blink_phase == blink_phase && cached.line == *line)
+                .unwrap_or_else(|| {
+                    let shaper =
+                        shaper.get_or_insert_with(|| LineShaper::new(settings, font_collection));
+                    CachedLine {
+                        line: line.clone(),
+                        blink_phase,
+                        layout: Rc::new(shaper.layout_line(line, text_blink_tick)),
+                    }
+                });
+
+            layouts.push(cached.layout.clone());
+            cached_lines.insert(line.key(), cached);
+        }
+
+        self.lines = cached_lines;
+        layouts
+    }
+}
+
+struct LineShaper {
+    paragraph_builder: ParagraphBuilder,
+    text_style: TextStyle,
+    // Indexed by boldness then italicness
+    font_families: [[Vec<String>; 2]; 2],
+    cell_size: (f32, f32),
+    builtin_glyphs: bool,
+}
+
+impl LineShaper {
+    fn new(settings: &LayoutSettings, font_collection: &FontCollection) -> Self {
+        let font = &settings.font;
+
+        let mut text_style = TextStyle::default();
+        text_style.set_font_size(settings.font_size);
+        for (feature, enabled) in &font.features {
+            text_style.add_font_feature(feature, *enabled as i32);
+        }
+
+        if let Some(line_height) = settings.line_height {
+            text_style.set_height_override(true);
+            text_style.set_height(line_height);
+        }
+
+        let mut style = ParagraphStyle::default();
+        style.set_text_style(&text_style);
+
+        Self {
+            paragraph_builder: ParagraphBuilder::new(&style, font_collection.clone()),
+            text_style,
+            font_families: [
+                [font.families(false, false), font.families(false, true)],
+                [font.families(true, false), font.families(true, true)],
+            ],
+            cell_size: settings.cell_size,
+            builtin_glyphs: font.builtin_glyphs,
+        }
+    }
+
+    fn layout_line(&mut self, line: &LineElement, text_blink_tick: usize) -> LineLayout {
+        let mut layout = LineLayout {
+            backgrounds: Vec::new(),
+            glyphs: Vec::new(),
+        };
+
+        for segment in line.clusters() {
+            let background = segment.background();
+            layout.backgrounds.push((
+                Rect::from_xywh(
+                    segment.first_column() as f32 * self.cell_size.0,
+                    0.,
+                    segment.width() as f32 * self.cell_size.0,
+                    self.cell_size.1,
+                ),
+                Color::from_rgb(background.0, background.1, background.2),
+            ));
+
+            self.set_segment_style(&segment, text_blink_tick);
+            self.layout_segment(&segment, &mut layout.glyphs);
+        }
+
+        layout
+    }
+
+    fn set_segment_style(&mut self, segment: &LineSegment, text_blink_tick: usize) {
+        let text_style = &mut self.text_style;
+
+        let is_hidden = segment.is_invisible()
+            || match segment.blink() {
+                Blink::None => false,
+                Blink::Slow => text_blink_tick / 2 % 2 == 1,
+                Blink::Rapid => text_blink_tick % 2 == 1,
+            };
+
+        let foreground = if is_hidden {
+            Color::TRANSPARENT
+        } else {
+            let foreground = segment.foreground();
+            Color::from_rgb(foreground.0, foreground.1, foreground.2)
+        };
+
+        text_style.set_color(foreground);
+
+        let (is_bold, is_italic) = (segment.is_bold(), segment.is_italic());
+        text_style.set_font_families(&self.font_families[is_bold as usize][is_italic as usize]);
+        text_style.set_font_style(match (is_bold, is_italic) {
+            (true, true) => FontStyle::bold_italic(),
+            (true, false) => FontStyle::bold(),
+            (false, true) => FontStyle::italic(),
+            (false, false) => FontStyle::normal(),
+        });
+
+        let mut decoration = TextDecoration::NO_DECORATION;
+        if segment.underline() != Underline::None {
+            decoration |= TextDecoration::UNDERLINE;
+        }
+        if segment.is_strikethrough() {
+            decoration |= TextDecoration::LINE_THROUGH;
+        }
+        if segment.is_overline() {
+            decoration |= TextDecoration::OVERLINE;
+        }
+        text_style.set_decoration_type(decoration);
+
+        // Skia draws every decoration of a run with the same style
+        // and colour, so the underline ones win
+        text_style.set_decoration_style(match segment.underline() {
+            Underline::Double => TextDecorationStyle::Double,
+            Underline::Curly => TextDecorationStyle::Wavy,
+            Underline::Dotted => TextDecorationStyle::Dotted,
+            Underline::Dashed => TextDecorationStyle::Dashed,
+            _ => TextDecorationStyle::Solid,
+        });
+        text_style.set_decoration_color(match segment.underline_color() {
+            Some(color) if !is_hidden => Color::from_rgb(color.0, color.1, color.2),
+            _ => foreground,
+        });
+    }
+
+    fn layout_text(&mut self, text: &str) -> Paragraph {
+        self.paragraph_builder.push_style(&self.text_style);
+        self.paragraph_builder.add_text(text);
+        let mut paragraph = self.paragraph_builder.build();
+        self.paragraph_builder.reset();
+        paragraph.layout(scalar::MAX);
+        paragraph
+    }
+
+    /// Lays out the text of a segment aligned to the grid. Glyphs not fitting
+    /// their cells (e.g. from fallback fonts) are centred, or scaled down, in
+    /// the cells they cover.
+    fn layout_segment(&mut self, segment: &LineSegment, glyphs: &mut Vec<Glyphs>) {
+        let cell_size = self.cell_size;
+
+        let text = segment.text();
+        if text.trim().is_empty()
+            && self.text_style.decoration_type() == TextDecoration::NO_DECORATION
+        {
+            return;
+        }
+
+        let has_builtin_glyphs = self.builtin_glyphs && text.chars().any(is_builtin_glyph);
+
+        // Shape the whole segment at once when it matches the grid, keeping
+        // ligatures intact
+        if !has_builtin_glyphs {
+            let paragraph = self.layout_text(&text);
+            let segment_width = segment.width() as f32 * cell_size.0;
+            if (paragraph.max_intrinsic_width() - segment_width).abs() < 0.5 {
+                glyphs.push(Glyphs::Text {
+                    paragraph,
+                    x: segment.first_column() as f32 * cell_size.0,
+                    y: 0.,
+                    scale: 1.,
+                });
+                return;
+            }
+        }
+
+        for (column, width, text) in segment.cells() {
+            let x = column as f32 * cell_size.0;
+            let cell_width = width as f32 * cell_size.0;
+
+            let mut chars = text.chars();
+            if let (Some(ch), None) = (chars.next(), chars.next()) {
+                if has_builtin_glyphs && is_builtin_glyph(ch) {
+                    glyphs.push(Glyphs::Builtin {
+                        ch,
+                        cell: Rect::from_xywh(x, 0., cell_width, cell_size.1),
+                        color: self.text_style.color(),
+                    });
+                    continue;
+                }
+            }
+
+            let paragraph = self.layout_text(&text);
+            let glyph_width = paragraph.max_intrinsic_width();
+
+            if glyph_width > cell_width {
+                let scale = cell_width / glyph_width;
+                glyphs.push(Glyphs::Text {
+                    paragraph,
+                    x,
+                    y: cell_size.1 * (1. - scale) / 2.,
+                    scale,
+                });
+            } else {
+                glyphs.push(Glyphs::Text {
+                    paragraph,
+                    x: x + (cell_width - glyph_width) / 2.,
+                    y: 0.,
+                    scale: 1.,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+pub mod app;
+pub mod cli;
+pub mod clipboard;
+pub mod components;
+pub mod config;
+pub mod error;
+pub mod fonts;
+pub mod glyphs;
+pub mod hooks;
+pub mod icons;
+pub mod layout;
+pub mod notifier;
+pub mod pane;
+pub mod process;
+pub mod rendering;
+pub mod selection;
+pub mod shell_integration;
+pub mod state;
+pub mod terminal_loop;
+pub mod utils;
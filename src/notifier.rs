@@ -0,0 +1,76 @@
+use serde::Deserialize;
+
+use crate::utils::run_command_detached;
+
+/// Where notifications are sent.
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifierKind {
+    // Desktop notifications (notify-send on Linux, osascript on macOS)
+    #[default]
+    Desktop,
+    // Only write notifications to the log
+    Log,
+    // Drop notifications
+    None,
+}
+
+pub trait Notifier {
+    fn notify(&self, title: &str, body: &str);
+}
+
+pub fn create_notifier(kind: NotifierKind) -> Box<dyn Notifier> {
+    match kind {
+        NotifierKind::Desktop => Box::new(DesktopNotifier),
+        NotifierKind::Log => Box::new(LogNotifier),
+        NotifierKind::None => Box::new(NoopNotifier),
+    }
+}
+
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn notify(&self, title: &str, body: &str) {
+        if cfg!(target_os = "macos") {
+            // The texts are passed as arguments so that they don't need to be
+            // escaped for AppleScript
+            let script = "on run argv\n\
+                          display notification (item 2 of argv) with title (item 1 of argv)\n\
+                          end run";
+            run_command_detached(
+                "osascript",
+                &[
+                    "-e".to_string(),
+                    script.to_string(),
+                    title.to_string(),
+                    body.to_string(),
+                ],
+            );
+        } else {
+            run_command_detached(
+                "notify-send",
+                &[
+                    "--app-name=Raven".to_string(),
+                    // A title starting with a dash isn't an option
+                    "--".to_string(),
+                    title.to_string(),
+                    body.to_string(),
+                ],
+            );
+        }
+    }
+}
+
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, title: &str, body: &str) {
+        log::info!("Notification: {}: {}", title, body);
+    }
+}
+
+pub struct NoopNotifier;
+
+impl Notifier for NoopNotifier {
+    fn notify(&self, _title: &str, _body: &str) {}
+}
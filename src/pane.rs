@@ -1,8 +1,11 @@
-use std::sync::Mutex;
+use std::{path::PathBuf, sync::Mutex};
 
 use wezterm_term::TerminalSize;
 
-use crate::terminal_loop::{create_terminal, TerminalBridge};
+use crate::{
+    config::{LaunchCommand, TerminalConfig},
+    terminal_loop::{create_terminal, ForegroundProcess, TerminalBridge},
+};
 
 pub type PaneId = usize;
 static PANE_ID: ::std::sync::atomic::AtomicUsize = ::std::sync::atomic::AtomicUsize::new(0);
@@ -15,6 +18,18 @@ pub struct Pane {
     pub id: PaneId,
     terminal_bridge: TerminalBridge,
     title: Mutex<String>,
+    // Title given by the user, takes precedence over the one set by the program
+    custom_title: Mutex<Option<String>>,
+    // Bells rung while the pane was in the background
+    unread_bells: Mutex<usize>,
+    // Working directory of the shell, when known
+    cwd: Mutex<Option<PathBuf>>,
+    // Exit code of the program while the pane is held open after it exited
+    exit_code: Mutex<Option<u32>>,
+    // Command the shell runs in the foreground, if any
+    foreground_command: Mutex<Option<String>>,
+    // Whether the program wrote output while the pane was in the background
+    has_activity: Mutex<bool>,
 }
 
 impl PartialEq for Pane {
@@ -24,24 +39,112 @@ impl PartialEq for Pane {
 }
 
 impl Pane {
-    pub fn new(id: PaneId, size: TerminalSize) -> anyhow::Result<Self> {
-        Ok(Self {
+    pub fn new(
+        id: PaneId,
+        size: TerminalSize,
+        config: &TerminalConfig,
+        command: LaunchCommand,
+    ) -> Self {
+        let cwd = command.cwd.clone();
+        Self {
             id,
-            terminal_bridge: create_terminal(size)?,
-            title: Mutex::new(format!("Terminal #{}", id)),
-        })
+            terminal_bridge: create_terminal(size, config.clone(), command),
+            title: Mutex::new(default_title(id)),
+            custom_title: Mutex::new(None),
+            unread_bells: Mutex::new(0),
+            cwd: Mutex::new(cwd),
+            exit_code: Mutex::new(None),
+            foreground_command: Mutex::new(None),
+            has_activity: Mutex::new(false),
+        }
     }
 
     pub fn title(&self) -> String {
+        if let Some(custom_title) = self.custom_title.lock().unwrap().as_ref() {
+            return custom_title.clone();
+        }
         self.title.lock().unwrap().clone()
     }
 
+    pub fn set_title(&self, title: String) {
+        let title = if title.trim().is_empty() {
+            default_title(self.id)
+        } else {
+            title
+        };
+        *self.title.lock().unwrap() = title;
+    }
+
+    pub fn rename(&self, custom_title: Option<String>) {
+        *self.custom_title.lock().unwrap() = custom_title.filter(|title| !title.trim().is_empty());
+    }
+
+    pub fn cwd(&self) -> Option<PathBuf> {
+        self.cwd.lock().unwrap().clone()
+    }
+
+    pub fn set_cwd(&self, cwd: PathBuf) {
+        *self.cwd.lock().unwrap() = Some(cwd);
+    }
+
+    pub fn exit_code(&self) -> Option<u32> {
+        *self.exit_code.lock().unwrap()
+    }
+
+    pub fn set_exit_code(&self, exit_code: Option<u32>) {
+        *self.exit_code.lock().unwrap() = exit_code;
+    }
+
+    pub fn foreground_command(&self) -> Option<String> {
+        self.foreground_command.lock().unwrap().clone()
+    }
+
+    pub fn set_foreground_command(&self, command: Option<String>) {
+        *self.foreground_command.lock().unwrap() = command;
+    }
+
+    pub fn has_activity(&self) -> bool {
+        *self.has_activity.lock().unwrap()
+    }
+
+    pub fn set_activity(&self, has_activity: bool) {
+        *self.has_activity.lock().unwrap() = has_activity;
+    }
+
+    pub fn unread_bells(&self) -> usize {
+        *self.unread_bells.lock().unwrap()
+    }
+
+    pub fn ring_bell(&self) {
+        *self.unread_bells.lock().unwrap() += 1;
+    }
+
+    pub fn clear_bells(&self) {
+        *self.unread_bells.lock().unwrap() = 0;
+    }
+
+    /// Program the shell is running, if any.
+    pub fn foreground_process(&self) -> ForegroundProcess {
+        self.terminal_bridge.foreground_process()
+    }
+
     pub fn terminal_bridge(&self) -> &TerminalBridge {
         &self.terminal_bridge
     }
 
+    /// Hangs up the program and stops the terminal.
+    /// Stops the terminal of the pane, without waiting for it on the UI
+    /// thread.
     pub fn close(&self) {
-        // TODO: Handle exiting in the main app state
-        std::process::exit(0);
+        let id = self.id;
+        self.terminal_bridge.close(move |result| {
+            if let Err(e) = result {
+                log::error!("Terminal of pane {} stopped on an error.\n{}", id, e);
+            }
+        });
     }
 }
+
+fn default_title(id: PaneId) -> String {
+    format!("Terminal #{}", id)
+}
@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+/// Working directory of a process.
+#[cfg(target_os = "linux")]
+pub fn process_cwd(pid: i32) -> Option<PathBuf> {
+    std::fs::read_link(format!("/proc/{}/cwd", pid)).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_cwd(_pid: i32) -> Option<PathBuf> {
+    None
+}
+
+/// Command line a process was started with, e.g. ["cargo", "build"].
+#[cfg(target_os = "linux")]
+pub fn process_cmdline(pid: i32) -> Option<Vec<String>> {
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).ok()?;
+    let args: Vec<String> = cmdline
+        .split(|byte| *byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+    (!args.is_empty()).then_some(args)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn process_cmdline(_pid: i32) -> Option<Vec<String>> {
+    None
+}
+
+/// Name of the command a process runs, e.g. "vim".
+#[cfg(target_os = "linux")]
+pub fn process_name(pid: i32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{}/comm", pid))
+        .ok()
+        .map(|name| name.trim_end().to_string())
+}
+
+#[cfg(target_os = "macos")]
+pub fn process_name(pid: i32) -> Option<String> {
+    let mut name = [0u8; 256];
+    // Safety: the buffer outlives the call, which writes at most its size
+    let len = unsafe { libc::proc_name(pid, name.as_mut_ptr().cast(), name.len() as u32) };
+    (len > 0).then(|| String::from_utf8_lossy(&name[..len as usize]).into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn process_name(_pid: i32) -> Option<String> {
+    None
+}
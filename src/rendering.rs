@@ -1,11 +1,43 @@
-use termwiz::cellcluster::CellCluster;
-use wezterm_term::{color::ColorPalette, CursorPosition, Line, Terminal};
+use std::sync::Arc;
+
+use termwiz::{cellcluster::CellCluster, surface::SequenceNo};
+use wezterm_term::{
+    color::{ColorAttribute, ColorPalette},
+    Blink, CursorPosition, Intensity, Line, StableRowIndex, Terminal, Underline,
+};
 
 #[derive(Clone, Debug)]
-pub struct LineElement(usize, Line, ColorPalette, usize);
+pub struct LineElement(usize, Line, Arc<ColorPalette>, usize, StableRowIndex);
 
 #[derive(Clone, Debug)]
-pub struct LineSegment(CellCluster, ColorPalette);
+pub struct LineSegment(CellCluster, Arc<ColorPalette>);
+
+/// Rows last sent to the UI, so only the ones that changed since get sent.
+#[derive(Default)]
+pub struct RenderedRows {
+    // Stable index and seqno of the line displayed at each visible row
+    rows: Vec<(StableRowIndex, SequenceNo)>,
+    cols: usize,
+    // Shared by all the lines until the palette changes
+    palette: Option<Arc<ColorPalette>>,
+}
+
+impl RenderedRows {
+    /// Forgets what was sent, so the next frame holds every row.
+    fn invalidate(&mut self) {
+        self.rows.clear();
+    }
+}
+
+/// Start of a command reported by shell integration.
+#[derive(Clone, PartialEq, Debug)]
+pub struct CommandMark<Row = usize> {
+    // Row of the command prompt. Visible row once rendered.
+    pub row: Row,
+    // Exit status of the command. None until it finishes or when the shell
+    // doesn't report it.
+    pub exit_status: Option<i32>,
+}
 
 impl LineElement {
     pub fn clusters(&self) -> Vec<LineSegment> {
@@ -24,24 +56,93 @@ impl LineElement {
     pub fn index(&self) -> usize {
         self.0
     }
+
+    /// Stable row and seqno of the line, telling it apart from the other
+    /// lines of the terminal and from its previous content.
+    pub fn key(&self) -> (StableRowIndex, SequenceNo) {
+        (self.4, self.1.current_seqno())
+    }
+
+    /// Whether some text of the line blinks, so it has to be redrawn
+    /// periodically.
+    pub fn has_blinking_text(&self) -> bool {
+        self.1
+            .visible_cells()
+            .any(|cell| cell.attrs().blink() != Blink::None)
+    }
 }
 
 impl LineSegment {
     pub fn is_bold(&self) -> bool {
-        match self.0.attrs.intensity() {
-            wezterm_term::Intensity::Bold => true,
-            wezterm_term::Intensity::Half => true,
-            _ => false,
+        self.0.attrs.intensity() == Intensity::Bold
+    }
+
+    pub fn is_dim(&self) -> bool {
+        self.0.attrs.intensity() == Intensity::Half
+    }
+
+    pub fn is_italic(&self) -> bool {
+        self.0.attrs.italic()
+    }
+
+    pub fn underline(&self) -> Underline {
+        self.0.attrs.underline()
+    }
+
+    /// Colour of the underline, when it differs from the foreground.
+    pub fn underline_color(&self) -> Option<(u8, u8, u8, u8)> {
+        match self.0.attrs.underline_color() {
+            ColorAttribute::Default => None,
+            color => Some(self.1.resolve_fg(color).as_rgba_u8()),
         }
     }
 
+    pub fn is_strikethrough(&self) -> bool {
+        self.0.attrs.strikethrough()
+    }
+
+    pub fn is_overline(&self) -> bool {
+        self.0.attrs.overline()
+    }
+
+    pub fn is_invisible(&self) -> bool {
+        self.0.attrs.invisible()
+    }
+
+    pub fn blink(&self) -> Blink {
+        self.0.attrs.blink()
+    }
+
+    /// Colour of the text, taking reverse video and dim text into account.
     pub fn foreground(&self) -> (u8, u8, u8, u8) {
-        let foreground = self.1.resolve_fg(self.0.attrs.foreground());
+        let foreground = if self.0.attrs.reverse() {
+            self.1.resolve_bg(self.0.attrs.background())
+        } else {
+            self.1.resolve_fg(self.0.attrs.foreground())
+        };
+
+        if self.is_dim() {
+            // Faint text sits halfway between its colour and the background
+            let background = self.background();
+            let (r, g, b, a) = foreground.as_rgba_u8();
+            return (
+                mix(r, background.0),
+                mix(g, background.1),
+                mix(b, background.2),
+                a,
+            );
+        }
+
         foreground.as_rgba_u8()
     }
 
+    /// Colour of the cells, taking reverse video into account.
     pub fn background(&self) -> (u8, u8, u8, u8) {
-        let background = self.1.resolve_bg(self.0.attrs.background());
+        let background = if self.0.attrs.reverse() {
+            self.1.resolve_fg(self.0.attrs.foreground())
+        } else {
+            self.1.resolve_bg(self.0.attrs.background())
+        };
         background.as_rgba_u8()
     }
 
@@ -49,41 +150,232 @@ impl LineSegment {
         self.0.width
     }
 
+    /// Column of the first cell of the segment.
+    pub fn first_column(&self) -> usize {
+        self.0.first_cell_idx
+    }
+
+    /// Text of each cell of the segment, with its column and the number of
+    /// columns it covers (2 for wide characters).
+    pub fn cells(&self) -> Vec<(usize, usize, String)> {
+        let mut cells: Vec<(usize, usize, String)> = vec![];
+
+        for (byte_idx, ch) in self.0.text.char_indices() {
+            let column = self.0.byte_to_cell_idx(byte_idx);
+            match cells.last_mut() {
+                // Combining characters belong to the cell they follow
+                Some((last_column, _, text)) if *last_column == column => text.push(ch),
+                _ => cells.push((
+                    column,
+                    self.0.byte_to_cell_width(byte_idx) as usize,
+                    ch.to_string(),
+                )),
+            }
+        }
+
+        cells
+    }
+
     pub fn text(&self) -> String {
         self.0.text.clone()
     }
 }
 
+fn mix(a: u8, b: u8) -> u8 {
+    ((a as u16 + b as u16) / 2) as u8
+}
+
 impl PartialEq for LineElement {
     fn eq(&self, other: &Self) -> bool {
-        self.1.current_seqno() == other.1.current_seqno() && self.1.as_str() == other.1.as_str()
+        // The seqno of a line changes along with its cells, so comparing the
+        // keys is enough to tell whether the content changed. It must also be
+        // laid out to the same width with the same palette.
+        self.key() == other.key()
+            && self.3 == other.3
+            && (Arc::ptr_eq(&self.2, &other.2) || self.2 == other.2)
     }
 }
 
+/// Renders the visible lines that changed since the previous frame, with
+/// their visible row, along with the number of visible lines.
 pub fn render_terminal(
     terminal: &Terminal,
     scroll_top: usize,
-) -> (Vec<LineElement>, CursorPosition) {
+    rendered_rows: &mut RenderedRows,
+) -> (Vec<(usize, LineElement)>, usize, CursorPosition) {
     let mut lines = vec![];
 
     let screen = terminal.screen();
     let first_visible_line_index = screen.scrollback_rows() - screen.physical_rows - scroll_top;
     let last_visible_line_index = first_visible_line_index + screen.physical_rows;
+
     let color_palette = terminal.get_config().color_palette();
+    let color_palette = match &rendered_rows.palette {
+        Some(palette) if **palette == color_palette => palette.clone(),
+        _ => {
+            rendered_rows.invalidate();
+            let palette = Arc::new(color_palette);
+            rendered_rows.palette = Some(palette.clone());
+            palette
+        }
+    };
+
+    if rendered_rows.cols != screen.physical_cols {
+        rendered_rows.invalidate();
+        rendered_rows.cols = screen.physical_cols;
+    }
+
+    let mut rows = Vec::with_capacity(screen.physical_rows + 1);
 
     terminal.screen().for_each_phys_line(|index, line| {
         if index < first_visible_line_index || index > last_visible_line_index {
             return;
         }
-        lines.push(LineElement(
-            index,
-            line.clone(),
-            color_palette.clone(),
-            screen.physical_cols,
-        ));
+
+        let row = index - first_visible_line_index;
+        let stable_row = screen.phys_to_stable_row_index(index);
+        let rendered_row = (stable_row, line.current_seqno());
+
+        if rendered_rows.rows.get(row) != Some(&rendered_row) {
+            lines.push((
+                row,
+                LineElement(
+                    index,
+                    line.clone(),
+                    color_palette.clone(),
+                    screen.physical_cols,
+                    stable_row,
+                ),
+            ));
+        }
+        rows.push(rendered_row);
     });
 
+    let line_count = rows.len();
+    rendered_rows.rows = rows;
+
     let cursor_position = terminal.cursor_pos();
 
-    (lines, cursor_position)
+    (lines, line_count, cursor_position)
+}
+
+/// Converts the marks of the commands, tracked with stable rows, to the
+/// marks visible on screen.
+pub fn render_command_marks(
+    terminal: &Terminal,
+    scroll_top: usize,
+    marks: &[CommandMark<StableRowIndex>],
+) -> Vec<CommandMark> {
+    let screen = terminal.screen();
+    let first_visible_line_index = screen.scrollback_rows() - screen.physical_rows - scroll_top;
+    let visible_lines = first_visible_line_index..first_visible_line_index + screen.physical_rows;
+
+    marks
+        .iter()
+        .filter_map(|mark| {
+            let line_index = screen.stable_row_to_phys(mark.row)?;
+            if !visible_lines.contains(&line_index) {
+                return None;
+            }
+            Some(CommandMark {
+                row: line_index - first_visible_line_index,
+                exit_status: mark.exit_status,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use wezterm_term::{TerminalConfiguration, TerminalSize};
+
+    use super::*;
+
+    const ROWS: usize = 5;
+
+    #[derive(Debug)]
+    struct TestConfig(ColorPalette);
+
+    impl TerminalConfiguration for TestConfig {
+        fn color_palette(&self) -> ColorPalette {
+            self.0.clone()
+        }
+    }
+
+    fn new_terminal(content: &str) -> Terminal {
+        let mut terminal = Terminal::new(
+            TerminalSize {
+                rows: ROWS,
+                cols: 20,
+                pixel_width: 0,
+                pixel_height: 0,
+                dpi: 1,
+            },
+            Arc::new(TestConfig(ColorPalette::default())),
+            "Raven",
+            env!("CARGO_PKG_VERSION"),
+            Box::new(std::io::sink()),
+        );
+        terminal.advance_bytes(content);
+        terminal
+    }
+
+    fn text(line: &LineElement) -> String {
+        line.1.as_str().trim_end().to_string()
+    }
+
+    fn rows(lines: &[(usize, LineElement)]) -> Vec<usize> {
+        lines.iter().map(|(row, _)| *row).collect()
+    }
+
+    #[test]
+    fn sends_only_the_rows_that_changed() {
+        let mut terminal = new_terminal("a\r\nb\r\nc");
+        let mut rendered_rows = RenderedRows::default();
+
+        let (lines, line_count, _) = render_terminal(&terminal, 0, &mut rendered_rows);
+        assert_eq!(line_count, ROWS);
+        assert_eq!(rows(&lines), (0..ROWS).collect::<Vec<_>>());
+
+        // Overwrites the second row
+        terminal.advance_bytes("\x1b[2;1Hx");
+        let (lines, line_count, _) = render_terminal(&terminal, 0, &mut rendered_rows);
+        assert_eq!(line_count, ROWS);
+        assert_eq!(rows(&lines), vec![1]);
+        assert_eq!(text(&lines[0].1), "x");
+
+        let (lines, _, _) = render_terminal(&terminal, 0, &mut rendered_rows);
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn sends_every_row_once_scrolled() {
+        let content = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+        let terminal = new_terminal(&content.join("\r\n"));
+        let mut rendered_rows = RenderedRows::default();
+        render_terminal(&terminal, 0, &mut rendered_rows);
+
+        // Each row now shows the line that was two rows above it
+        let (lines, line_count, _) = render_terminal(&terminal, 2, &mut rendered_rows);
+        assert_eq!(rows(&lines), (0..line_count).collect::<Vec<_>>());
+        assert_eq!(text(&lines[0].1), "3");
+
+        let (lines, _, _) = render_terminal(&terminal, 2, &mut rendered_rows);
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn sends_every_row_when_the_palette_changes() {
+        let mut terminal = new_terminal("a\r\nb");
+        let mut rendered_rows = RenderedRows::default();
+        render_terminal(&terminal, 0, &mut rendered_rows);
+
+        let mut palette = ColorPalette::default();
+        palette.foreground = palette.background;
+        terminal.set_config(Arc::new(TestConfig(palette.clone())));
+
+        let (lines, _, _) = render_terminal(&terminal, 0, &mut rendered_rows);
+        assert_eq!(rows(&lines), (0..ROWS).collect::<Vec<_>>());
+        assert!(lines.iter().all(|(_, line)| *line.2 == palette));
+    }
 }
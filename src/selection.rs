@@ -1,5 +1,6 @@
 use skia_safe::Rect;
 use termwiz::surface::SequenceNo;
+use wezterm_term::{Screen, SemanticZone};
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct Selection {
@@ -14,6 +15,19 @@ pub struct SelectionRange {
 }
 
 impl Selection {
+    /// Selection covering a semantic zone (prompt, input or output).
+    pub fn from_zone(zone: &SemanticZone, screen: &Screen, seqno: SequenceNo) -> Option<Self> {
+        let start_line = screen.stable_row_to_phys(zone.start_y)?;
+        let end_line = screen.stable_row_to_phys(zone.end_y)?;
+
+        Some(Self {
+            seqno,
+            start: (zone.start_x, start_line),
+            // Zones are inclusive of their last cell
+            end: (zone.end_x + 1, end_line),
+        })
+    }
+
     pub fn range(&self) -> SelectionRange {
         let is_reverse_selection = (self.start.0 > self.end.0 && self.start.1 == self.end.1)
             || (self.start.1 > self.end.1);
@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use portable_pty::CommandBuilder;
+
+use crate::utils::shell_quote;
+
+const BASH_INTEGRATION: &str = include_str!("../assets/shell-integration/raven.bash");
+const ZSH_INTEGRATION: &str = include_str!("../assets/shell-integration/raven.zsh");
+const FISH_INTEGRATION: &str = include_str!("../assets/shell-integration/raven.fish");
+
+/// Sets up the command so the shell loads Raven's shell integration on top
+/// of the user's own config. Shells other than bash, zsh and fish are left
+/// untouched.
+pub fn inject_shell_integration(cmd: &mut CommandBuilder, shell: &str) {
+    let Some(shell_name) = Path::new(shell).file_name().and_then(|name| name.to_str()) else {
+        return;
+    };
+
+    let result = match shell_name {
+        "bash" => inject_bash(cmd),
+        "zsh" => inject_zsh(cmd),
+        "fish" => inject_fish(cmd),
+        _ => return,
+    };
+
+    if let Err(e) = result {
+        log::warn!(
+            "Unable to set up shell integration for {}. Continuing without it...\n{}",
+            shell_name,
+            e
+        );
+    }
+}
+
+fn integration_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("raven")
+        .join("shell-integration")
+}
+
+fn inject_bash(cmd: &mut CommandBuilder) -> anyhow::Result<()> {
+    let dir = integration_dir().join("bash");
+    std::fs::create_dir_all(&dir)?;
+
+    let script = dir.join("raven.bash");
+    std::fs::write(&script, BASH_INTEGRATION)?;
+
+    // bash has no way to load an extra file, so replace the rc file with one
+    // loading the user's bashrc before the integration
+    let rcfile = dir.join("bashrc");
+    std::fs::write(
+        &rcfile,
+        format!(
+            "if [ -f ~/.bashrc ]; then . ~/.bashrc; fi\n. {}\n",
+            shell_quote(&script.to_string_lossy())
+        ),
+    )?;
+
+    cmd.arg("--rcfile");
+    cmd.arg(rcfile);
+    Ok(())
+}
+
+fn inject_zsh(cmd: &mut CommandBuilder) -> anyhow::Result<()> {
+    let dir = integration_dir().join("zsh");
+    std::fs::create_dir_all(&dir)?;
+
+    let script = dir.join("raven.zsh");
+    std::fs::write(&script, ZSH_INTEGRATION)?;
+
+    // zsh reads its config from $ZDOTDIR, point it to files that restore the
+    // user's ZDOTDIR and load their config before the integration
+    std::fs::write(
+        dir.join(".zshenv"),
+        "if [[ -f \"${RAVEN_ORIGINAL_ZDOTDIR:-$HOME}/.zshenv\" ]]; then\n  \
+         source \"${RAVEN_ORIGINAL_ZDOTDIR:-$HOME}/.zshenv\"\nfi\n",
+    )?;
+    std::fs::write(
+        dir.join(".zshrc"),
+        format!(
+            "ZDOTDIR=\"${{RAVEN_ORIGINAL_ZDOTDIR:-$HOME}}\"\n\
+             unset RAVEN_ORIGINAL_ZDOTDIR\n\
+             if [[ -f \"$ZDOTDIR/.zshrc\" ]]; then\n  source \"$ZDOTDIR/.zshrc\"\nfi\n\
+             source {}\n",
+            shell_quote(&script.to_string_lossy())
+        ),
+    )?;
+
+    if let Ok(original_zdotdir) = std::env::var("ZDOTDIR") {
+        cmd.env("RAVEN_ORIGINAL_ZDOTDIR", original_zdotdir);
+    }
+    cmd.env("ZDOTDIR", dir);
+    Ok(())
+}
+
+fn inject_fish(cmd: &mut CommandBuilder) -> anyhow::Result<()> {
+    let dir = integration_dir().join("fish");
+    let conf_dir = dir.join("fish").join("vendor_conf.d");
+    std::fs::create_dir_all(&conf_dir)?;
+
+    std::fs::write(conf_dir.join("raven.fish"), FISH_INTEGRATION)?;
+
+    // fish loads every vendor_conf.d found in $XDG_DATA_DIRS
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .unwrap_or_else(|_| String::from("/usr/local/share:/usr/share"));
+    cmd.env(
+        "XDG_DATA_DIRS",
+        format!("{}:{}", dir.to_string_lossy(), data_dirs),
+    );
+    Ok(())
+}
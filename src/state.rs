@@ -1,9 +1,39 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
 use wezterm_term::TerminalSize;
 
-use crate::pane::{alloc_pane_id, Pane, PaneId};
+use crate::{
+    config::{ConfirmCloseConfig, LaunchProfile, TerminalConfig},
+    pane::{alloc_pane_id, Pane, PaneId},
+    terminal_loop::ForegroundProcess,
+};
 
+/// Closing of a pane, or of the whole app.
+#[derive(Clone, PartialEq)]
+pub enum CloseRequest {
+    Pane(PaneId),
+    App,
+}
+
+/// Closing waiting for the user to confirm, as programs run in the panes.
+#[derive(Clone, PartialEq)]
+pub struct PendingClose {
+    pub request: CloseRequest,
+    pub programs: Vec<String>,
+}
+
+impl PendingClose {
+    /// Question asked to the user.
+    pub fn prompt(&self) -> String {
+        let programs = self.programs.join(", ");
+        match self.request {
+            CloseRequest::Pane(_) => format!("Close the pane running {}? (y/n)", programs),
+            CloseRequest::App => format!("Quit with {} still running? (y/n)", programs),
+        }
+    }
+}
+
+#[derive(Default)]
 pub struct AppState {
     panes: Vec<Arc<Pane>>,
     active_pane_id: Option<PaneId>,
@@ -25,31 +55,176 @@ impl AppState {
 
     pub fn set_active_pane(&mut self, pane_id: PaneId) {
         self.active_pane_id.replace(pane_id);
+
+        if let Some(pane) = self.pane(pane_id) {
+            pane.clear_bells();
+            pane.set_activity(false);
+        }
+    }
+
+    pub fn active_pane_id(&self) -> Option<PaneId> {
+        self.active_pane_id
+    }
+
+    pub fn pane(&self, pane_id: PaneId) -> Option<Arc<Pane>> {
+        self.panes.iter().find(|pane| pane.id == pane_id).cloned()
+    }
+
+    pub fn set_pane_title(&mut self, pane_id: PaneId, title: String) {
+        if let Some(pane) = self.pane(pane_id) {
+            pane.set_title(title);
+        }
+    }
+
+    pub fn ring_pane_bell(&mut self, pane_id: PaneId) {
+        if self.active_pane_id == Some(pane_id) {
+            return;
+        }
+
+        if let Some(pane) = self.pane(pane_id) {
+            pane.ring_bell();
+        }
+    }
+
+    pub fn mark_pane_activity(&mut self, pane_id: PaneId) {
+        if self.active_pane_id == Some(pane_id) {
+            return;
+        }
+
+        if let Some(pane) = self.pane(pane_id) {
+            pane.set_activity(true);
+        }
+    }
+
+    pub fn set_pane_foreground_command(&mut self, pane_id: PaneId, command: Option<String>) {
+        if let Some(pane) = self.pane(pane_id) {
+            pane.set_foreground_command(command);
+        }
+    }
+
+    pub fn rename_pane(&mut self, pane_id: PaneId, custom_title: Option<String>) {
+        if let Some(pane) = self.pane(pane_id) {
+            pane.rename(custom_title);
+        }
     }
 
     pub fn panes(&self) -> Vec<Arc<Pane>> {
         self.panes.clone()
     }
 
-    pub fn new_pane(&mut self) -> Arc<Pane> {
+    pub fn set_pane_exit_code(&mut self, pane_id: PaneId, exit_code: Option<u32>) {
+        if let Some(pane) = self.pane(pane_id) {
+            pane.set_exit_code(exit_code);
+        }
+    }
+
+    /// Programs closing would kill, that the user has to confirm.
+    pub fn programs_to_confirm(
+        &self,
+        request: &CloseRequest,
+        config: &ConfirmCloseConfig,
+    ) -> Vec<String> {
+        if !config.enabled {
+            return Vec::new();
+        }
+
+        let panes = match request {
+            CloseRequest::Pane(pane_id) => self.pane(*pane_id).into_iter().collect(),
+            CloseRequest::App => self.panes(),
+        };
+
+        panes
+            .iter()
+            .filter_map(|pane| match pane.foreground_process() {
+                ForegroundProcess::Program => None,
+                ForegroundProcess::Other {
+                    name: Some(name), ..
+                } => (!config.can_close(&name)).then_some(name),
+                // Whatever it is, it may not be safe to kill
+                ForegroundProcess::Other { name: None, .. } => {
+                    Some(String::from("an unknown program"))
+                }
+            })
+            .collect()
+    }
+
+    pub fn close(&mut self, request: &CloseRequest) {
+        match request {
+            CloseRequest::Pane(pane_id) => self.close_pane(*pane_id),
+            CloseRequest::App => {
+                for pane in self.panes() {
+                    self.close_pane(pane.id);
+                }
+            }
+        }
+    }
+
+    /// Closes the pane, activating the next one if it was the active pane.
+    pub fn close_pane(&mut self, pane_id: PaneId) {
+        let Some(index) = self.panes.iter().position(|pane| pane.id == pane_id) else {
+            return;
+        };
+
+        let pane = self.panes.remove(index);
+        pane.close();
+
+        if self.active_pane_id == Some(pane_id) {
+            self.active_pane_id = None;
+            let next_pane = self.panes.get(index).or(self.panes.last());
+            if let Some(next_pane_id) = next_pane.map(|pane| pane.id) {
+                self.set_active_pane(next_pane_id);
+            }
+        }
+    }
+
+    pub fn set_pane_cwd(&mut self, pane_id: PaneId, cwd: PathBuf) {
+        if let Some(pane) = self.pane(pane_id) {
+            pane.set_cwd(cwd);
+        }
+    }
+
+    /// Opens a new pane in the directory of the active pane and activates it.
+    pub fn open_pane(
+        &mut self,
+        config: &TerminalConfig,
+        profile: Option<&LaunchProfile>,
+    ) -> Arc<Pane> {
+        let cwd = self.active_pane().and_then(|pane| pane.cwd());
+        let pane = self.new_pane(config, profile, cwd);
+        self.set_active_pane(pane.id);
+        pane
+    }
+
+    /// Creates a pane running the given profile, or the default program.
+    /// The pane opens in `cwd` unless the profile has its own directory.
+    pub fn new_pane(
+        &mut self,
+        config: &TerminalConfig,
+        profile: Option<&LaunchProfile>,
+        cwd: Option<PathBuf>,
+    ) -> Arc<Pane> {
         let pane_id = alloc_pane_id();
 
+        let mut command = config.launch_command(profile);
+        if command.cwd.is_none() {
+            command.cwd = cwd;
+        }
+
         let initial_rows = 24;
         let initial_cols = 80;
 
-        let pane = Arc::new(
-            Pane::new(
-                pane_id,
-                TerminalSize {
-                    rows: initial_rows,
-                    cols: initial_cols,
-                    pixel_width: 0,
-                    pixel_height: 0,
-                    dpi: 1,
-                },
-            )
-            .unwrap(),
-        );
+        let pane = Arc::new(Pane::new(
+            pane_id,
+            TerminalSize {
+                rows: initial_rows,
+                cols: initial_cols,
+                pixel_width: 0,
+                pixel_height: 0,
+                dpi: 1,
+            },
+            config,
+            command,
+        ));
 
         self.panes.push(pane.clone());
 
@@ -1,60 +1,219 @@
 use std::{
-    sync::Arc,
+    ffi::OsString,
+    io::Write,
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
+use base64::Engine;
 use config::{Palette, RgbaColor};
 use filedescriptor::{poll, pollfd, POLLIN};
-use flume::{unbounded, Receiver, Selector, Sender};
-use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
+use flume::{bounded, unbounded, Receiver, Selector, Sender};
+use portable_pty::{native_pty_system, Child, CommandBuilder, ExitStatus, MasterPty, PtySize};
 use termwiz::escape::{
     csi::{DecPrivateMode, DecPrivateModeCode, Device, Mode},
-    Action, CSI,
+    osc::FinalTermSemanticPrompt,
+    Action, OperatingSystemCommand, CSI,
 };
 use wezterm_term::{
-    color::ColorPalette, CursorPosition, KeyCode, KeyModifiers, MouseEvent, Terminal,
+    color::ColorPalette, Alert, AlertHandler, Clipboard, ClipboardSelection, CursorPosition,
+    KeyCode, KeyModifiers, MouseEvent, SemanticType, StableRowIndex, Terminal,
     TerminalConfiguration, TerminalSize,
 };
 
 use crate::{
-    rendering::{render_terminal, LineElement},
+    config::{LaunchCommand, Osc52Config, TerminalConfig},
+    error::TerminalError,
+    process::{process_cmdline, process_cwd, process_name},
+    rendering::{render_command_marks, render_terminal, CommandMark, LineElement, RenderedRows},
     selection::Selection,
+    shell_integration::inject_shell_integration,
+    utils::{hostname, percent_decode},
 };
 
-pub fn create_terminal(size: TerminalSize) -> anyhow::Result<TerminalBridge> {
-    let terminal_loop = TerminalLoop::new(size)?;
+/// Starts a terminal running the given command. Failing to start it is
+/// reported with `TerminalEvent::Error`, like the errors it stops on.
+pub fn create_terminal(
+    size: TerminalSize,
+    config: TerminalConfig,
+    command: LaunchCommand,
+) -> TerminalBridge {
+    let terminal_loop = match TerminalLoop::new(size, config, command) {
+        Ok(terminal_loop) => terminal_loop,
+        Err(e) => return TerminalBridge::failed(e),
+    };
 
     let user_event_tx = terminal_loop.user_event_channel.0.clone();
+    let terminal_event_tx = terminal_loop.terminal_event_channel.0.clone();
     let terminal_event_rx = terminal_loop.terminal_event_channel.1.clone();
+    let frames_in_flight = terminal_loop.frame_limiter.frames_in_flight.clone();
+    let foreground_process = terminal_loop.foreground_process.clone();
 
-    tokio::spawn(async {
-        terminal_loop.run().ok();
-    });
+    // The loop blocks while waiting for events, so it gets a thread of its own
+    let thread = std::thread::Builder::new()
+        .name(String::from("terminal-loop"))
+        .spawn(move || {
+            terminal_loop.run().map_err(|e| {
+                let error = TerminalError::stopped(e);
+                terminal_event_tx
+                    .send(TerminalEvent::Error(error.clone()))
+                    .ok();
+                error
+            })
+        });
 
-    Ok(TerminalBridge(user_event_tx, terminal_event_rx))
+    match thread {
+        Ok(thread) => TerminalBridge {
+            user_event_tx,
+            terminal_event_rx,
+            frames_in_flight,
+            foreground_process,
+            thread: Mutex::new(Some(thread)),
+        },
+        Err(e) => TerminalBridge::failed(TerminalError::Thread(e.to_string())),
+    }
 }
 
-pub struct TerminalBridge(Sender<UserEvent>, Receiver<TerminalEvent>);
+pub struct TerminalBridge {
+    user_event_tx: Sender<UserEvent>,
+    terminal_event_rx: Receiver<TerminalEvent>,
+    frames_in_flight: Arc<AtomicUsize>,
+    foreground_process: Arc<Mutex<ForegroundProcess>>,
+    // Taken when the loop is joined
+    thread: Mutex<Option<std::thread::JoinHandle<Result<(), TerminalError>>>>,
+}
 
 impl TerminalBridge {
+    /// Bridge of a terminal that failed to start, only holding the error.
+    fn failed(error: TerminalError) -> Self {
+        let (terminal_event_tx, terminal_event_rx) = unbounded();
+        terminal_event_tx.send(TerminalEvent::Error(error)).ok();
+
+        Self {
+            user_event_tx: unbounded().0,
+            terminal_event_rx,
+            frames_in_flight: Arc::new(AtomicUsize::new(0)),
+            foreground_process: Arc::default(),
+            thread: Mutex::new(None),
+        }
+    }
+
     pub fn user_event_sender(&self) -> &Sender<UserEvent> {
-        &self.0
+        &self.user_event_tx
     }
 
     pub fn terminal_event_receiver(&self) -> &Receiver<TerminalEvent> {
-        &self.1
+        &self.terminal_event_rx
+    }
+
+    /// Tells the terminal loop a redraw was handled, so it can send the next
+    /// one.
+    pub fn frame_handled(&self) {
+        self.frames_in_flight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |frames| {
+                frames.checked_sub(1)
+            })
+            .ok();
+    }
+
+    /// What runs in the foreground, as last seen by the terminal loop. Reading
+    /// it doesn't wait for the loop, however busy it is.
+    pub fn foreground_process(&self) -> ForegroundProcess {
+        self.foreground_process.lock().unwrap().clone()
+    }
+
+    /// Stops the terminal loop, if still running, without waiting for it to
+    /// end. The loop is joined on another thread, which then calls
+    /// `on_stopped` with the error the loop stopped on, if any.
+    pub fn close(&self, on_stopped: impl FnOnce(Result<(), TerminalError>) + Send + 'static) {
+        // The loop is gone already when the program exited
+        self.user_event_tx.send(UserEvent::Close).ok();
+
+        let Some(thread) = self.thread.lock().unwrap().take() else {
+            return;
+        };
+        let joined = std::thread::Builder::new()
+            .name(String::from("terminal-closer"))
+            .spawn(move || {
+                let result = thread.join().unwrap_or_else(|_| {
+                    Err(TerminalError::Stopped(String::from(
+                        "the terminal loop panicked",
+                    )))
+                });
+                on_stopped(result);
+            });
+        // The loop is left detached then, it still stops on its own
+        if let Err(e) = joined {
+            log::warn!("Unable to wait for the terminal loop to stop.\n{}", e);
+        }
     }
 }
 
+/// What runs in the foreground of a terminal.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ForegroundProcess {
+    // The program the terminal started, or nothing once it exited
+    #[default]
+    Program,
+    // Another process group, e.g. vim run from the shell. Its name is unknown
+    // when it can't be read, e.g. on platforms without /proc
+    Other {
+        pid: libc::pid_t,
+        name: Option<String>,
+    },
+}
+
 pub enum TerminalEvent {
     Redraw {
-        lines: Vec<LineElement>,
+        // Lines that changed since the previous redraw, with their visible row
+        lines: Vec<(usize, LineElement)>,
+        // Number of visible lines
+        line_count: usize,
         cursor: CursorPosition,
         scroll_top: usize,
         selection: Option<Selection>,
         terminal_visible_size: (usize, usize),
+        command_marks: Vec<CommandMark>,
     },
     SetClipboardContent(String),
+    // A program asked to read the clipboard (OSC 52).
+    // Answered with `UserEvent::ClipboardContent`.
+    RequestClipboardContent,
+    // The program changed the title with OSC 0/1/2.
+    TitleChanged(String),
+    // The working directory changed, reported with OSC 7 or read from the
+    // foreground process.
+    CwdChanged(PathBuf),
+    // The program rang the bell (BEL).
+    Bell,
+    // The program asked for a desktop notification (OSC 9 / OSC 777).
+    Notification {
+        title: Option<String>,
+        body: String,
+    },
+    // A command finished running, reported by shell integration (OSC 133).
+    CommandFinished {
+        duration: Duration,
+        exit_status: Option<i32>,
+    },
+    // Command running in the foreground, e.g. "cargo build". None when the
+    // program the pane started is back in the foreground.
+    ForegroundCommandChanged(Option<String>),
+    // The program wrote some output. Reported at most once per second.
+    Activity,
+    // The program exited. The exit code is unknown when it couldn't be read.
+    ProcessExited {
+        exit_code: Option<u32>,
+    },
+    // The program was started again after exiting
+    ProcessRestarted,
+    // The terminal failed to start or stopped on an error
+    Error(TerminalError),
     Exit,
 }
 
@@ -66,64 +225,255 @@ pub enum UserEvent {
     Scroll(f64),
     Mouse(MouseEvent),
     RequestRedraw,
+    ClipboardContent(Option<String>),
+    // Scroll to the previous (negative) or next (positive) prompt
+    ScrollToPrompt(isize),
+    // Select the output of the command at the given visible row
+    SelectCommandOutput(usize),
+    // Maximum number of redraws per second
+    SetFrameRate(u32),
+    // Stop the loop and hang up the program, the pane is closing
+    Close,
 }
 
+// Redraws per second until the UI reports the refresh rate of the display
+const DEFAULT_FRAME_RATE: u32 = 60;
+
+// Batches of actions read from the PTY the loop can lag behind by
+const PTY_ACTIONS_CAPACITY: usize = 4;
+
+// How often to check which command runs in the foreground
+const FOREGROUND_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Shortest time between two reports of output
+const ACTIVITY_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+// Longest time to wait for the UI to handle a redraw before sending the next
+const MAX_FRAME_WAIT: Duration = Duration::from_secs(1);
+
 enum TerminalLoopData {
     UserEvent(UserEvent),
     PtyActions(Vec<Action>),
     ManualRedrawRequest,
+    PtyClosed,
 }
 
 pub struct TerminalExtraState {
     scroll_top: usize,
     selection: Option<Selection>,
     is_dragging: bool,
+    command_started_at: Option<Instant>,
+    command_marks: Vec<CommandMark<StableRowIndex>>,
+    cwd: Option<PathBuf>,
+    // Whether the shell reports a local working directory with OSC 7
+    is_cwd_reported: bool,
+    rendered_rows: RenderedRows,
+    foreground_command: Option<String>,
+    foreground_checked_at: Instant,
+    activity_reported_at: Option<Instant>,
 }
 
 struct TerminalLoop {
     terminal: Terminal,
+    config: TerminalConfig,
     pty: Box<dyn MasterPty + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    pty_writer: SharedWriter,
     user_event_channel: (Sender<UserEvent>, Receiver<UserEvent>),
     terminal_event_channel: (Sender<TerminalEvent>, Receiver<TerminalEvent>),
     manual_redraw_channel: (Sender<()>, Receiver<()>),
+    alert_channel: (Sender<Alert>, Receiver<Alert>),
     extra_state: TerminalExtraState,
+    // Command the program was started with, to restart it
+    command: LaunchCommand,
+    frame_limiter: FrameLimiter,
+    // Shared with the bridge, so the UI can read it without waiting
+    foreground_process: Arc<Mutex<ForegroundProcess>>,
 }
 
-impl TerminalLoop {
-    pub fn new(size: TerminalSize) -> anyhow::Result<Self> {
-        let pty_system = native_pty_system();
-        let pty = pty_system.openpty(PtySize {
-            rows: size.rows as u16,
-            cols: size.cols as u16,
-            pixel_width: size.pixel_width as u16,
-            pixel_height: size.pixel_height as u16,
-        })?;
+/// Program started in a new PTY.
+struct SpawnedProgram {
+    pty: Box<dyn MasterPty + Send>,
+    child: Box<dyn Child + Send + Sync>,
+    writer: Box<dyn Write + Send>,
+}
+
+fn spawn_program(
+    command: &LaunchCommand,
+    config: &TerminalConfig,
+    size: PtySize,
+) -> Result<SpawnedProgram, TerminalError> {
+    let pty_system = native_pty_system();
+    let pty = pty_system.openpty(size).map_err(TerminalError::open_pty)?;
+
+    let mut cmd = CommandBuilder::new(&command.program);
+    cmd.args(&command.args);
+    cmd.env("TERM", "xterm-256color");
+    cmd.env("COLORTERM", "truecolor");
+    cmd.env("TERM_PROGRAM", "Raven");
+    cmd.env("TERM_PROGRAM_VERSION", env!("CARGO_PKG_VERSION"));
+
+    for (key, value) in &command.env {
+        cmd.env(key, value);
+    }
+
+    if let Some(cwd) = &command.cwd {
+        cmd.cwd(cwd);
+    }
+
+    // Only inject in interactive shells, arguments could be a script to run
+    if config.shell_integration && command.args.is_empty() {
+        inject_shell_integration(&mut cmd, &command.program);
+    }
+
+    let child = pty
+        .slave
+        .spawn_command(cmd)
+        .map_err(|e| TerminalError::spawn(&command.program, e))?;
+
+    let writer = pty.master.take_writer().map_err(TerminalError::pty)?;
+
+    Ok(SpawnedProgram {
+        pty: pty.master,
+        child,
+        writer,
+    })
+}
 
-        let shell = std::env::var("SHELL").unwrap_or(String::from("bash"));
+/// Paces the redraws to the frame rate, and holds them back while the UI
+/// is still handling the previous one. Content changing in the meantime is
+/// sent with the next redraw.
+struct FrameLimiter {
+    frame_interval: Duration,
+    last_frame_at: Option<Instant>,
+    // Whether the content changed since the last redraw
+    is_pending: bool,
+    // Redraws sent to the UI it hasn't handled yet
+    frames_in_flight: Arc<AtomicUsize>,
+}
 
-        let mut cmd = CommandBuilder::new(shell);
-        cmd.env("TERM_PROGRAM", "Raven");
+impl FrameLimiter {
+    fn new() -> Self {
+        Self {
+            frame_interval: Duration::from_secs(1) / DEFAULT_FRAME_RATE,
+            last_frame_at: None,
+            is_pending: false,
+            frames_in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
 
-        pty.slave.spawn_command(cmd)?;
-        let terminal = Terminal::new(
+    fn set_frame_rate(&mut self, frame_rate: u32) {
+        self.frame_interval = Duration::from_secs(1) / frame_rate.max(1);
+    }
+
+    fn request_frame(&mut self) {
+        self.is_pending = true;
+    }
+
+    /// Whether the UI is still busy with the previous redraw. Gives up
+    /// waiting after a while so a lost redraw doesn't freeze the pane.
+    fn is_ui_behind(&self) -> bool {
+        self.frames_in_flight.load(Ordering::Relaxed) > 0
+            && self
+                .last_frame_at
+                .is_some_and(|last_frame_at| last_frame_at.elapsed() < MAX_FRAME_WAIT)
+    }
+
+    /// When to check again for a pending redraw, if any.
+    fn deadline(&self) -> Option<Instant> {
+        if !self.is_pending {
+            return None;
+        }
+
+        let next_frame_at = match self.last_frame_at {
+            Some(last_frame_at) => last_frame_at + self.frame_interval,
+            None => Instant::now(),
+        };
+
+        // Check again one frame later while the UI is behind
+        if self.is_ui_behind() {
+            return Some(next_frame_at.max(Instant::now() + self.frame_interval));
+        }
+        Some(next_frame_at)
+    }
+
+    fn is_frame_due(&self) -> bool {
+        self.is_pending
+            && !self.is_ui_behind()
+            && self
+                .last_frame_at
+                .is_none_or(|last_frame_at| last_frame_at.elapsed() >= self.frame_interval)
+    }
+
+    fn frame_sent(&mut self) {
+        self.is_pending = false;
+        self.last_frame_at = Some(Instant::now());
+        self.frames_in_flight.store(1, Ordering::Relaxed);
+    }
+}
+
+impl TerminalLoop {
+    pub fn new(
+        size: TerminalSize,
+        config: TerminalConfig,
+        command: LaunchCommand,
+    ) -> Result<Self, TerminalError> {
+        let program = spawn_program(
+            &command,
+            &config,
+            PtySize {
+                rows: size.rows as u16,
+                cols: size.cols as u16,
+                pixel_width: size.pixel_width as u16,
+                pixel_height: size.pixel_height as u16,
+            },
+        )?;
+
+        let pty_writer = SharedWriter::new(program.writer)?;
+        let mut terminal = Terminal::new(
             size,
             Arc::new(TermConfig::new()),
             "Raven",
-            "1.0.0",
-            pty.master.take_writer()?,
+            env!("CARGO_PKG_VERSION"),
+            Box::new(pty_writer.clone()),
         );
 
+        let terminal_event_channel = unbounded();
+        let clipboard: Arc<dyn Clipboard> = Arc::new(Osc52Clipboard {
+            terminal_event_tx: terminal_event_channel.0.clone(),
+            config: config.osc52.clone(),
+        });
+        terminal.set_clipboard(&clipboard);
+
+        let alert_channel = unbounded();
+        terminal.set_notification_handler(Box::new(TerminalAlertHandler(alert_channel.0.clone())));
+
         Ok(Self {
             terminal,
-            pty: pty.master,
+            config,
+            pty: program.pty,
+            child: program.child,
+            pty_writer,
             user_event_channel: unbounded(),
-            terminal_event_channel: unbounded(),
+            terminal_event_channel,
             manual_redraw_channel: unbounded(),
+            alert_channel,
             extra_state: TerminalExtraState {
                 scroll_top: 0,
                 selection: None,
                 is_dragging: false,
+                command_started_at: None,
+                command_marks: Vec::new(),
+                cwd: command.cwd.clone(),
+                is_cwd_reported: false,
+                rendered_rows: RenderedRows::default(),
+                foreground_command: None,
+                foreground_checked_at: Instant::now(),
+                activity_reported_at: None,
             },
+            command,
+            frame_limiter: FrameLimiter::new(),
+            foreground_process: Arc::default(),
         })
     }
 
@@ -137,7 +487,7 @@ impl TerminalLoop {
                         pixel_width: size.pixel_width as u16,
                         pixel_height: size.pixel_height as u16,
                     })
-                    .unwrap();
+                    .map_err(TerminalError::pty)?;
                 self.terminal.resize(size);
             }
             UserEvent::Paste(content) => {
@@ -217,11 +567,228 @@ impl TerminalLoop {
             UserEvent::RequestRedraw => {
                 self.manual_redraw_channel.0.send(())?;
             }
+            UserEvent::SetFrameRate(frame_rate) => {
+                self.frame_limiter.set_frame_rate(frame_rate);
+            }
+            // Handled by the run loop
+            UserEvent::Close => {}
+            UserEvent::ScrollToPrompt(direction) => {
+                self.scroll_to_prompt(direction)?;
+            }
+            UserEvent::SelectCommandOutput(row) => {
+                self.select_command_output(row)?;
+            }
+            UserEvent::ClipboardContent(content) => {
+                let content = content.unwrap_or_default();
+                let encoded = base64::engine::general_purpose::STANDARD.encode(content);
+                write!(self.pty_writer, "\x1b]52;c;{}\x1b\\", encoded)?;
+                self.pty_writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handles the actions that the terminal model doesn't support before
+    /// forwarding the rest to it.
+    fn handle_pty_actions(&mut self, actions: Vec<Action>) -> anyhow::Result<()> {
+        let mut unhandled_actions = Vec::with_capacity(actions.len());
+
+        for action in actions {
+            match action {
+                Action::OperatingSystemCommand(osc)
+                    if matches!(*osc, OperatingSystemCommand::QuerySelection(_)) =>
+                {
+                    self.handle_clipboard_query()?;
+                }
+                Action::OperatingSystemCommand(osc)
+                    if matches!(*osc, OperatingSystemCommand::FinalTermSemanticPrompt(_)) =>
+                {
+                    // Bring the terminal model up to date first so the
+                    // prompt is tracked at the right cursor position
+                    self.terminal
+                        .perform_actions(std::mem::take(&mut unhandled_actions));
+                    self.terminal
+                        .perform_actions(vec![Action::OperatingSystemCommand(osc.clone())]);
+
+                    if let OperatingSystemCommand::FinalTermSemanticPrompt(prompt) = *osc {
+                        self.handle_semantic_prompt(prompt)?;
+                    }
+                }
+                action => unhandled_actions.push(action),
+            }
+        }
+
+        self.terminal.perform_actions(unhandled_actions);
+
+        while let Ok(alert) = self.alert_channel.1.try_recv() {
+            self.handle_alert(alert)?;
+        }
+
+        Ok(())
+    }
+
+    fn update_cwd(&mut self, cwd: PathBuf) -> anyhow::Result<()> {
+        if self.extra_state.cwd.as_ref() == Some(&cwd) {
+            return Ok(());
+        }
+
+        self.extra_state.cwd = Some(cwd.clone());
+        self.terminal_event_channel
+            .0
+            .send(TerminalEvent::CwdChanged(cwd))?;
+        Ok(())
+    }
+
+    fn handle_semantic_prompt(&mut self, prompt: FinalTermSemanticPrompt) -> anyhow::Result<()> {
+        match prompt {
+            FinalTermSemanticPrompt::MarkEndOfInputAndStartOfOutput { .. } => {
+                self.extra_state.command_started_at = Some(Instant::now());
+            }
+            FinalTermSemanticPrompt::CommandStatus { status, .. } => {
+                if let Some(mark) = self.extra_state.command_marks.last_mut() {
+                    mark.exit_status = Some(status);
+                }
+                self.finish_command(Some(status))?;
+            }
+            // Shells without status reporting only mark the next prompt
+            FinalTermSemanticPrompt::FreshLineAndStartPrompt { .. }
+            | FinalTermSemanticPrompt::StartPrompt(_) => {
+                self.finish_command(None)?;
+                self.mark_command_start();
+            }
+            _ => {}
         }
 
         Ok(())
     }
 
+    fn mark_command_start(&mut self) {
+        let screen = self.terminal.screen();
+        let row = screen.visible_row_to_stable_row(self.terminal.cursor_pos().y);
+        let first_row = screen.phys_to_stable_row_index(0);
+
+        let marks = &mut self.extra_state.command_marks;
+        // Forget about the commands that went out of the scrollback
+        marks.retain(|mark| mark.row >= first_row);
+
+        // Multi-line prompts report the start of the prompt on every line
+        if marks.last().map(|mark| mark.row == row).unwrap_or(false) {
+            return;
+        }
+        marks.push(CommandMark {
+            row,
+            exit_status: None,
+        });
+    }
+
+    fn scroll_to_prompt(&mut self, direction: isize) -> anyhow::Result<()> {
+        let zones = self.terminal.get_semantic_zones()?;
+        let screen = self.terminal.screen();
+        let max_offset = screen.scrollback_rows() - screen.physical_rows;
+        let first_visible_line_index = max_offset - self.extra_state.scroll_top;
+        let top_row = screen.phys_to_stable_row_index(first_visible_line_index);
+
+        let mut prompt_rows = zones
+            .iter()
+            .filter(|zone| zone.semantic_type == SemanticType::Prompt)
+            .map(|zone| zone.start_y);
+
+        let target_row = if direction < 0 {
+            prompt_rows.rev().find(|row| *row < top_row)
+        } else {
+            prompt_rows.find(|row| *row > top_row)
+        };
+
+        let Some(line_index) = target_row.and_then(|row| screen.stable_row_to_phys(row)) else {
+            return Ok(());
+        };
+
+        self.extra_state.scroll_top = max_offset.saturating_sub(line_index);
+        self.handle_user_event(UserEvent::RequestRedraw)
+    }
+
+    fn select_command_output(&mut self, row: usize) -> anyhow::Result<()> {
+        let (_, line_index) = self.visible_xy_to_absolute_xy(0, row);
+        let seqno = self.terminal.current_seqno();
+        let zones = self.terminal.get_semantic_zones()?;
+        let screen = self.terminal.screen();
+        let stable_row = screen.phys_to_stable_row_index(line_index);
+
+        // Output of the command under the row, or the one following the
+        // prompt under the row
+        let selection = zones
+            .iter()
+            .filter(|zone| zone.semantic_type == SemanticType::Output)
+            .find(|zone| zone.end_y >= stable_row)
+            .and_then(|zone| Selection::from_zone(zone, screen, seqno));
+
+        if selection.is_some() {
+            self.extra_state.selection = selection;
+            self.handle_user_event(UserEvent::RequestRedraw)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish_command(&mut self, exit_status: Option<i32>) -> anyhow::Result<()> {
+        if let Some(started_at) = self.extra_state.command_started_at.take() {
+            self.terminal_event_channel
+                .0
+                .send(TerminalEvent::CommandFinished {
+                    duration: started_at.elapsed(),
+                    exit_status,
+                })?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_alert(&mut self, alert: Alert) -> anyhow::Result<()> {
+        match alert {
+            Alert::WindowTitleChanged(_) | Alert::IconTitleChanged(_) => {
+                let title = self.terminal.get_title().to_string();
+                self.terminal_event_channel
+                    .0
+                    .send(TerminalEvent::TitleChanged(title))?;
+            }
+            Alert::CurrentWorkingDirectoryChanged => {
+                let cwd = self
+                    .terminal
+                    .get_current_dir()
+                    .and_then(|url| local_dir(url.host_str(), url.path()));
+
+                self.extra_state.is_cwd_reported = cwd.is_some();
+                if let Some(cwd) = cwd {
+                    self.update_cwd(cwd)?;
+                }
+            }
+            Alert::Bell => {
+                self.terminal_event_channel.0.send(TerminalEvent::Bell)?;
+            }
+            Alert::ToastNotification { title, body, .. } => {
+                self.terminal_event_channel
+                    .0
+                    .send(TerminalEvent::Notification { title, body })?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_clipboard_query(&mut self) -> anyhow::Result<()> {
+        if !self.config.osc52.allow_read {
+            log::info!("Denied OSC 52 clipboard read. Enable osc52.allow_read to allow it.");
+            return Ok(());
+        }
+
+        self.terminal_event_channel
+            .0
+            .send(TerminalEvent::RequestClipboardContent)?;
+        Ok(())
+    }
+
     fn visible_xy_to_absolute_xy(&self, x: usize, y: usize) -> (usize, usize) {
         let screen = self.terminal.screen();
         let first_visible_line_index =
@@ -232,7 +799,13 @@ impl TerminalLoop {
     fn handle_redraw(&mut self) -> anyhow::Result<()> {
         let scroll_top = self.extra_state.scroll_top;
         let terminal_event_tx = self.terminal_event_channel.0.clone();
-        let (lines, cursor) = render_terminal(&self.terminal, scroll_top);
+        let (lines, line_count, cursor) = render_terminal(
+            &self.terminal,
+            scroll_top,
+            &mut self.extra_state.rendered_rows,
+        );
+        let command_marks =
+            render_command_marks(&self.terminal, scroll_top, &self.extra_state.command_marks);
 
         let is_selection_seqno_mismatch = self
             .extra_state
@@ -249,33 +822,230 @@ impl TerminalLoop {
 
         terminal_event_tx.send(TerminalEvent::Redraw {
             lines,
+            line_count,
             cursor,
             scroll_top,
             selection: self.extra_state.selection.clone(),
             terminal_visible_size: (screen.physical_cols, screen.physical_rows),
+            command_marks,
         })?;
+        self.frame_limiter.frame_sent();
+        Ok(())
+    }
+
+    /// Exit status of the program once it exited, None while it runs. The
+    /// status is unknown when it couldn't be read.
+    fn program_exit_status(&mut self) -> Option<Option<ExitStatus>> {
+        match self.child.try_wait() {
+            Ok(exit_status) => exit_status.map(Some),
+            Err(e) => {
+                log::warn!("Unable to get the exit status of the program.\n{}", e);
+                Some(None)
+            }
+        }
+    }
+
+    /// Reports the exit status of the program, and tells whether to keep the
+    /// pane open.
+    fn handle_program_exit(&mut self, exit_status: Option<ExitStatus>) -> anyhow::Result<bool> {
+        self.terminal_event_channel
+            .0
+            .send(TerminalEvent::ProcessExited {
+                exit_code: exit_status.as_ref().map(ExitStatus::exit_code),
+            })?;
+
+        let failed = exit_status.as_ref().is_none_or(|s| !s.success());
+        if !self.command.hold && !(failed && self.config.hold_on_failure) {
+            return Ok(false);
+        }
+
+        let reason = match &exit_status {
+            Some(exit_status) => match exit_status.signal() {
+                Some(signal) => format!("killed by {}", signal),
+                None => format!("exited with code {}", exit_status.exit_code()),
+            },
+            None => String::from("exited"),
+        };
+        self.terminal.advance_bytes(format!(
+            "\r\n\x1b[0m[process {} \u{2014} press Enter to restart]\r\n",
+            reason
+        ));
+        self.frame_limiter.request_frame();
+        Ok(true)
+    }
+
+    /// Starts the program again in a new PTY, keeping the content of the
+    /// terminal.
+    fn restart_program(&mut self) -> Result<PtyReadThread, TerminalError> {
+        let size = self.pty.get_size().unwrap_or_default();
+        let program = spawn_program(&self.command, &self.config, size)?;
+
+        self.pty = program.pty;
+        self.child = program.child;
+        self.pty_writer.replace(program.writer)?;
+
+        self.terminal_event_channel
+            .0
+            .send(TerminalEvent::ProcessRestarted)
+            .ok();
+
+        PtyReadThread::new(&self.pty)
+    }
+
+    /// Process group running in the foreground, when it isn't the program.
+    fn foreground_group(&mut self) -> Option<libc::pid_t> {
+        if !matches!(self.child.try_wait(), Ok(None)) {
+            return None;
+        }
+
+        let foreground_group = self.pty.process_group_leader()?;
+        if self.child.process_id() == Some(foreground_group as u32) {
+            return None;
+        }
+        Some(foreground_group)
+    }
+
+    /// Checked whenever output is read, as well as polled, for the UI to
+    /// know right away when a program starts or leaves the foreground.
+    fn update_foreground_process(&mut self) {
+        let foreground_group = self.foreground_group();
+        let mut foreground_process = self.foreground_process.lock().unwrap();
+
+        *foreground_process = match foreground_group {
+            None => ForegroundProcess::Program,
+            Some(group) => match &*foreground_process {
+                // Only read the name again when the group changes
+                ForegroundProcess::Other { pid, .. } if *pid == group => return,
+                _ => ForegroundProcess::Other {
+                    pid: group,
+                    name: process_name(group),
+                },
+            },
+        };
+    }
+
+    fn update_foreground_command(&mut self) -> anyhow::Result<()> {
+        self.extra_state.foreground_checked_at = Instant::now();
+        self.update_foreground_process();
+
+        let command = self
+            .foreground_group()
+            .and_then(process_cmdline)
+            .map(|args| args.join(" "));
+        if command == self.extra_state.foreground_command {
+            return Ok(());
+        }
+
+        self.extra_state.foreground_command = command.clone();
+        self.terminal_event_channel
+            .0
+            .send(TerminalEvent::ForegroundCommandChanged(command))?;
+        Ok(())
+    }
+
+    fn report_activity(&mut self) -> anyhow::Result<()> {
+        let is_reported = self
+            .extra_state
+            .activity_reported_at
+            .is_some_and(|reported_at| reported_at.elapsed() < ACTIVITY_REPORT_INTERVAL);
+        if is_reported {
+            return Ok(());
+        }
+
+        self.extra_state.activity_reported_at = Some(Instant::now());
+        self.terminal_event_channel
+            .0
+            .send(TerminalEvent::Activity)?;
         Ok(())
     }
 
+    /// Sends SIGHUP to the program and to the job running in the foreground,
+    /// like closing a terminal does.
+    fn hang_up(&mut self) {
+        // The pid may be reused once the program exited
+        if !matches!(self.child.try_wait(), Ok(None)) {
+            return;
+        }
+
+        let program_group = self.child.process_id().map(|pid| pid as libc::pid_t);
+        let foreground_group = self.pty.process_group_leader();
+
+        for group in [program_group, foreground_group].into_iter().flatten() {
+            // Safety: sending a signal has no memory safety requirements
+            unsafe {
+                libc::killpg(group, libc::SIGHUP);
+            }
+            if foreground_group == program_group {
+                break;
+            }
+        }
+    }
+
     pub fn run(mut self) -> anyhow::Result<()> {
-        let pty_read_thread = PtyReadThread::new(&self.pty);
-        let terminal_actions_rx = pty_read_thread.actions();
+        let mut pty_read_thread = PtyReadThread::new(&self.pty)?;
         let user_event_rx = self.user_event_channel.1.clone();
         let manual_redraw_rx = self.manual_redraw_channel.1.clone();
         let terminal_event_tx = self.terminal_event_channel.0.clone();
 
+        let mut pty_closed = false;
+        let mut program_exited = false;
+
         loop {
-            let data = Selector::new()
-                .recv(&terminal_actions_rx, |maybe_actions| {
-                    maybe_actions.map(|actions| TerminalLoopData::PtyActions(actions))
-                })
+            if self.frame_limiter.is_frame_due() {
+                self.handle_redraw()?;
+            }
+
+            // The PTY can stay open after the program exits, e.g. when a job
+            // it left in the background still uses it, so its exit is polled
+            if self.extra_state.foreground_checked_at.elapsed() >= FOREGROUND_POLL_INTERVAL {
+                if !program_exited {
+                    if let Some(exit_status) = self.program_exit_status() {
+                        program_exited = true;
+                        if !self.handle_program_exit(exit_status)? {
+                            terminal_event_tx.send(TerminalEvent::Exit)?;
+                            break;
+                        }
+                    }
+                }
+                self.update_foreground_command()?;
+
+                // Without a local directory reported with OSC 7, e.g. from a
+                // shell without integration or on a remote host, follow the
+                // directory of the foreground process
+                if !self.extra_state.is_cwd_reported {
+                    if let Some(cwd) = self.pty.process_group_leader().and_then(process_cwd) {
+                        self.update_cwd(cwd)?;
+                    }
+                }
+            }
+            let foreground_check_at =
+                self.extra_state.foreground_checked_at + FOREGROUND_POLL_INTERVAL;
+
+            let mut selector = Selector::new()
                 .recv(&user_event_rx, |maybe_event| {
                     maybe_event.map(|event| TerminalLoopData::UserEvent(event))
                 })
                 .recv(&manual_redraw_rx, |maybe_event| {
                     maybe_event.map(|_| TerminalLoopData::ManualRedrawRequest)
-                })
-                .wait();
+                });
+
+            // Once the PTY closed, only user events are left to handle
+            if !pty_closed {
+                selector = selector.recv(pty_read_thread.actions(), |maybe_actions| {
+                    Ok(maybe_actions
+                        .map(|actions| TerminalLoopData::PtyActions(actions))
+                        .unwrap_or(TerminalLoopData::PtyClosed))
+                });
+            }
+
+            // Wake up when the next redraw or foreground check is due
+            let deadline = match self.frame_limiter.deadline() {
+                Some(frame_deadline) => frame_deadline.min(foreground_check_at),
+                None => foreground_check_at,
+            };
+            let Ok(data) = selector.wait_deadline(deadline) else {
+                continue;
+            };
 
             let Ok(data) = data else {
                 terminal_event_tx.send(TerminalEvent::Exit)?;
@@ -283,15 +1053,50 @@ impl TerminalLoop {
             };
 
             match data {
+                // The reader thread stops once the program exits
+                TerminalLoopData::UserEvent(UserEvent::Close) => {
+                    self.hang_up();
+                    return Ok(());
+                }
+                TerminalLoopData::UserEvent(UserEvent::Keydown(KeyCode::Enter, _))
+                    if program_exited =>
+                {
+                    let closed_read_thread =
+                        std::mem::replace(&mut pty_read_thread, self.restart_program()?);
+                    closed_read_thread.close();
+                    pty_closed = false;
+                    program_exited = false;
+                }
+                // There is no program left to send input to
+                TerminalLoopData::UserEvent(UserEvent::Keydown(..) | UserEvent::Paste(_))
+                    if program_exited => {}
                 TerminalLoopData::PtyActions(actions) => {
-                    self.terminal.perform_actions(actions);
-                    self.handle_redraw()?;
+                    self.handle_pty_actions(actions)?;
+                    self.update_foreground_process();
+                    self.report_activity()?;
+                    self.frame_limiter.request_frame();
                 }
                 TerminalLoopData::UserEvent(event) => {
                     self.handle_user_event(event)?;
                 }
                 TerminalLoopData::ManualRedrawRequest => {
-                    self.handle_redraw()?;
+                    self.frame_limiter.request_frame();
+                }
+                TerminalLoopData::PtyClosed => {
+                    pty_closed = true;
+                    // A program still exiting is reported by the next poll
+                    let exit_status = if program_exited {
+                        None
+                    } else {
+                        self.program_exit_status()
+                    };
+                    if let Some(exit_status) = exit_status {
+                        program_exited = true;
+                        if !self.handle_program_exit(exit_status)? {
+                            terminal_event_tx.send(TerminalEvent::Exit)?;
+                            break;
+                        }
+                    }
                 }
             }
         }
@@ -301,18 +1106,35 @@ impl TerminalLoop {
     }
 }
 
+/// Directory of an OSC 7 `file://` URL, when it is on this machine. Shells
+/// send their host name along with it, which `Url::to_file_path` rejects.
+fn local_dir(host: Option<&str>, path: &str) -> Option<PathBuf> {
+    let is_local = match host {
+        None | Some("") | Some("localhost") => true,
+        Some(host) => hostname().is_some_and(|hostname| host.eq_ignore_ascii_case(&hostname)),
+    };
+    if !is_local || !path.starts_with('/') {
+        return None;
+    }
+
+    Some(PathBuf::from(OsString::from_vec(percent_decode(path))))
+}
+
 struct PtyReadThread {
-    thread: std::thread::JoinHandle<()>,
     actions_rx: Receiver<Vec<Action>>,
 }
 
 impl PtyReadThread {
-    pub fn new(pty: &Box<dyn MasterPty + Send>) -> Self {
-        let mut reader = pty.try_clone_reader().unwrap();
-        let pty_raw_fd = pty.as_raw_fd().unwrap();
-        let (tx, rx) = unbounded();
+    pub fn new(pty: &Box<dyn MasterPty + Send>) -> Result<Self, TerminalError> {
+        let mut reader = pty.try_clone_reader().map_err(TerminalError::pty)?;
+        let pty_raw_fd = pty
+            .as_raw_fd()
+            .ok_or_else(|| TerminalError::Pty(String::from("no file descriptor to poll")))?;
+        // Once the loop is behind, reading stops until it catches up, so the
+        // program gets blocked writing instead of buffering without bound
+        let (tx, rx) = bounded(PTY_ACTIONS_CAPACITY);
 
-        let thread = std::thread::spawn(move || {
+        std::thread::spawn(move || {
             let delay = Duration::from_millis(3);
             let mut buf = vec![0u8; 128 * 1024];
 
@@ -321,8 +1143,10 @@ impl PtyReadThread {
             let mut action_size = 0;
             let mut hold = false;
             let mut deadline = None;
+            // Whether the loop went away
+            let mut closed = false;
 
-            loop {
+            while !closed {
                 match reader.read(&mut buf) {
                     Ok(size) if size == 0 => {
                         break;
@@ -339,8 +1163,8 @@ impl PtyReadThread {
                                 ))) => {
                                     hold = true;
                                     // Flush prior actions
-                                    if !actions.is_empty() {
-                                        tx.send(std::mem::take(&mut actions)).unwrap();
+                                    if !actions.is_empty() && !closed {
+                                        closed = tx.send(std::mem::take(&mut actions)).is_err();
                                         action_size = 0;
                                     }
                                 }
@@ -394,7 +1218,7 @@ impl PtyReadThread {
                                 }
                             }
 
-                            tx.send(std::mem::take(&mut actions)).unwrap();
+                            closed = tx.send(std::mem::take(&mut actions)).is_err();
                             deadline = None;
                             action_size = 0;
                         }
@@ -402,23 +1226,127 @@ impl PtyReadThread {
                 }
             }
 
-            if !actions.is_empty() {
-                tx.send(std::mem::take(&mut actions)).unwrap();
+            if !actions.is_empty() && !closed {
+                tx.send(std::mem::take(&mut actions)).ok();
             }
         });
 
-        Self {
-            thread,
-            actions_rx: rx,
-        }
+        Ok(Self { actions_rx: rx })
     }
 
     pub fn actions(&self) -> &Receiver<Vec<Action>> {
         &self.actions_rx
     }
 
+    /// Stops the thread once it's done with its current read. It isn't
+    /// waited for: a job left in the background by the program can keep the
+    /// PTY open, and the read blocked, long after the pane is gone.
     pub fn close(self) {
-        let _ = self.thread.join();
+        // Unblock the thread if it's waiting for room in the channel
+        drop(self.actions_rx);
+    }
+}
+
+/// Pty writer shared between the terminal model and the loop, so the loop
+/// can answer requests the terminal model doesn't handle.
+///
+/// Writes are handed to a thread of their own: a program echoing its input,
+/// e.g. `cat` given a large paste, stops reading it while its output isn't
+/// read, which would block the loop for good if it wrote itself.
+#[derive(Clone)]
+struct SharedWriter(Arc<Mutex<Sender<Vec<u8>>>>);
+
+impl SharedWriter {
+    fn new(writer: Box<dyn Write + Send>) -> Result<Self, TerminalError> {
+        let input_tx = spawn_pty_write_thread(writer)?;
+        Ok(Self(Arc::new(Mutex::new(input_tx))))
+    }
+
+    /// Writes to the PTY of a restarted program from now on.
+    fn replace(&self, writer: Box<dyn Write + Send>) -> Result<(), TerminalError> {
+        *self.0.lock().unwrap() = spawn_pty_write_thread(writer)?;
+        Ok(())
+    }
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        // The thread only stops once writing failed, the program is gone and
+        // the input has nowhere to go
+        self.0.lock().unwrap().send(buf.to_vec()).ok();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the input sent to the returned channel to the PTY, until the
+/// channel is dropped or writing fails.
+fn spawn_pty_write_thread(
+    mut writer: Box<dyn Write + Send>,
+) -> Result<Sender<Vec<u8>>, TerminalError> {
+    let (input_tx, input_rx) = unbounded::<Vec<u8>>();
+
+    std::thread::Builder::new()
+        .name(String::from("pty-writer"))
+        .spawn(move || {
+            for input in input_rx.iter() {
+                if let Err(e) = writer.write_all(&input).and_then(|_| writer.flush()) {
+                    log::warn!("Unable to write to the PTY.\n{}", e);
+                    break;
+                }
+            }
+        })
+        .map_err(|e| TerminalError::Thread(e.to_string()))?;
+
+    Ok(input_tx)
+}
+
+/// Forwards the alerts raised by the terminal model while performing
+/// actions back to the loop.
+struct TerminalAlertHandler(Sender<Alert>);
+
+impl AlertHandler for TerminalAlertHandler {
+    fn alert(&mut self, alert: Alert) {
+        self.0.send(alert).ok();
+    }
+}
+
+/// Receives the clipboard writes requested with OSC 52.
+struct Osc52Clipboard {
+    terminal_event_tx: Sender<TerminalEvent>,
+    config: Osc52Config,
+}
+
+impl Clipboard for Osc52Clipboard {
+    fn set_contents(
+        &self,
+        _selection: ClipboardSelection,
+        data: Option<String>,
+    ) -> anyhow::Result<()> {
+        let Some(data) = data else {
+            return Ok(());
+        };
+
+        if !self.config.allow_write {
+            log::info!("Denied OSC 52 clipboard write. Enable osc52.allow_write to allow it.");
+            return Ok(());
+        }
+
+        if data.len() > self.config.max_size {
+            log::warn!(
+                "Denied OSC 52 clipboard write of {} bytes. Limit is {} bytes.",
+                data.len(),
+                self.config.max_size
+            );
+            return Ok(());
+        }
+
+        self.terminal_event_tx
+            .send(TerminalEvent::SetClipboardContent(data))?;
+        Ok(())
     }
 }
 
@@ -472,3 +1400,66 @@ impl TerminalConfiguration for TermConfig {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_wait_for_the_ui() {
+        let mut frame_limiter = FrameLimiter::new();
+        // Only the frames in flight hold the redraws back
+        frame_limiter.frame_interval = Duration::ZERO;
+        assert!(!frame_limiter.is_frame_due());
+
+        frame_limiter.request_frame();
+        assert!(frame_limiter.is_frame_due());
+        frame_limiter.frame_sent();
+        assert!(!frame_limiter.is_frame_due());
+
+        frame_limiter.request_frame();
+        assert!(!frame_limiter.is_frame_due());
+        assert!(frame_limiter.deadline().is_some());
+
+        // The UI handled the redraw
+        frame_limiter.frames_in_flight.store(0, Ordering::Relaxed);
+        assert!(frame_limiter.is_frame_due());
+        frame_limiter.frame_sent();
+
+        // A redraw the UI never handled stops holding the next ones back
+        frame_limiter.request_frame();
+        frame_limiter.last_frame_at = Instant::now().checked_sub(MAX_FRAME_WAIT);
+        assert!(frame_limiter.is_frame_due());
+    }
+
+    #[test]
+    fn local_dir_accepts_local_hosts() {
+        let dir = Some(PathBuf::from("/home/user"));
+        assert_eq!(local_dir(None, "/home/user"), dir);
+        assert_eq!(local_dir(Some(""), "/home/user"), dir);
+        assert_eq!(local_dir(Some("localhost"), "/home/user"), dir);
+        if let Some(hostname) = hostname() {
+            assert_eq!(local_dir(Some(&hostname), "/home/user"), dir);
+            assert_eq!(local_dir(Some(&hostname.to_uppercase()), "/home/user"), dir);
+        }
+    }
+
+    #[test]
+    fn local_dir_rejects_remote_hosts() {
+        assert_eq!(local_dir(Some("remote.invalid"), "/home/user"), None);
+        assert_eq!(local_dir(None, "relative/path"), None);
+    }
+
+    #[test]
+    fn local_dir_decodes_the_path() {
+        assert_eq!(
+            local_dir(None, "/tmp/my%20dir/%C3%A9"),
+            Some(PathBuf::from("/tmp/my dir/é"))
+        );
+        // Paths don't have to be valid UTF-8
+        assert_eq!(
+            local_dir(None, "/tmp/%FF"),
+            Some(PathBuf::from(OsString::from_vec(b"/tmp/\xFF".to_vec())))
+        );
+    }
+}
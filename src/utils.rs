@@ -1,16 +1,22 @@
+use std::{cell::RefCell, collections::HashMap, path::Path};
+
 use skia_safe::{
     scalar,
-    textlayout::{FontCollection, Paragraph, ParagraphBuilder, ParagraphStyle, TextStyle},
-    FontMgr,
+    textlayout::{Paragraph, ParagraphBuilder, ParagraphStyle, TextStyle},
 };
 
-pub fn create_paragraph(text: &str, font_size: f32, line_height: Option<f32>) -> Paragraph {
-    let mut font_collection = FontCollection::new();
-    font_collection.set_default_font_manager(FontMgr::default(), "jetbrains mono");
+use crate::{config::FontConfig, fonts::font_collection};
 
+pub fn create_paragraph(
+    text: &str,
+    font: &FontConfig,
+    font_size: f32,
+    line_height: Option<f32>,
+) -> Paragraph {
     let mut style = ParagraphStyle::default();
     let mut text_style = TextStyle::default();
     text_style.set_font_size(font_size);
+    text_style.set_font_families(&font.families(false, false));
 
     if let Some(height) = line_height {
         text_style.set_height(height);
@@ -18,7 +24,7 @@ pub fn create_paragraph(text: &str, font_size: f32, line_height: Option<f32>) ->
     }
     style.set_text_style(&text_style);
 
-    let mut paragraph_builder = ParagraphBuilder::new(&style, font_collection);
+    let mut paragraph_builder = ParagraphBuilder::new(&style, font_collection());
 
     paragraph_builder.add_text(text);
 
@@ -29,7 +35,117 @@ pub fn create_paragraph(text: &str, font_size: f32, line_height: Option<f32>) ->
     paragraph
 }
 
-pub fn get_cell_size(font_size: f32, line_height: Option<f32>) -> (f32, f32) {
-    let paragraph = create_paragraph("T", font_size, line_height);
-    (paragraph.min_intrinsic_width(), paragraph.height())
+thread_local! {
+    // Cell sizes by font families, font size and line height
+    static CELL_SIZES: RefCell<HashMap<(Vec<String>, u32, Option<u32>), (f32, f32)>> =
+        RefCell::new(HashMap::new());
+}
+
+pub fn get_cell_size(font: &FontConfig, font_size: f32, line_height: Option<f32>) -> (f32, f32) {
+    let key = (
+        font.families(false, false),
+        font_size.to_bits(),
+        line_height.map(f32::to_bits),
+    );
+
+    CELL_SIZES.with_borrow_mut(|cell_sizes| {
+        *cell_sizes.entry(key).or_insert_with(|| {
+            let paragraph = create_paragraph("T", font, font_size, line_height);
+            (paragraph.min_intrinsic_width(), paragraph.height())
+        })
+    })
+}
+
+/// Runs a command in the background, without waiting for it to finish.
+pub fn run_command_detached(program: &str, args: &[String]) {
+    let mut command = std::process::Command::new(program);
+    command.args(args);
+
+    let program = program.to_string();
+    // Wait on a separate thread so the child gets reaped once it's done
+    std::thread::spawn(move || {
+        if let Err(e) = command.status() {
+            log::warn!("Unable to run {}.\n{}", program, e);
+        }
+    });
+}
+
+/// Path for display, with the home directory shortened to `~`.
+pub fn display_path(path: &Path) -> String {
+    if let Some(relative_path) = dirs::home_dir().and_then(|home| path.strip_prefix(home).ok()) {
+        if relative_path.as_os_str().is_empty() {
+            return String::from("~");
+        }
+        return format!("~/{}", relative_path.to_string_lossy());
+    }
+    path.to_string_lossy().into_owned()
+}
+
+/// Decodes the `%XX` escapes of a URL part, leaving invalid ones as they are.
+pub fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(byte) = std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+
+    decoded
+}
+
+/// Quotes the text for POSIX shells, unless it only has safe characters.
+pub fn shell_quote(text: &str) -> String {
+    let is_safe = text
+        .chars()
+        .all(|ch| ch.is_alphanumeric() || "/._-+,:@".contains(ch));
+
+    if is_safe {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+/// Host name of this machine, as shells report it along with their working
+/// directory.
+pub fn hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    // Safety: the buffer outlives the call, which writes at most its size
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return None;
+    }
+    let len = name.iter().position(|byte| *byte == 0)?;
+    Some(String::from_utf8_lossy(&name[..len]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_percent_escapes() {
+        assert_eq!(percent_decode("/a%20b/%C3%A9"), "/a b/é".as_bytes());
+        // Invalid and truncated escapes are kept as they are
+        assert_eq!(percent_decode("100%zz%2"), b"100%zz%2");
+    }
+
+    #[test]
+    fn quotes_paths_for_the_shell() {
+        assert_eq!(shell_quote("/tmp/a-1_b.png"), "/tmp/a-1_b.png");
+        assert_eq!(shell_quote("/tmp/my file"), "'/tmp/my file'");
+        assert_eq!(shell_quote("$HOME/`id`"), "'$HOME/`id`'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
 }